@@ -4,7 +4,7 @@ use std::sync::Arc;
 use anyhow::Result;
 use axum::{extract::State, routing::get, Json, Router};
 use clap::Parser;
-use flipbook::flipbook::package::FlipbookPackage;
+use flipbook::flipbook::package::{self, FlipbookPackage};
 use serde::Serialize;
 use tower_http::compression::CompressionLayer;
 use tower_http::services::ServeDir;
@@ -83,7 +83,8 @@ async fn gather_all_metadata(state: Arc<AppState>) -> Result<Json<AllFlipbooks>,
 
         let s = tokio::fs::read_to_string(entry.path()).await?;
 
-        let flipbook: FlipbookPackage = match serde_json::from_str(&s) {
+        // Older versions of the metadata are upgraded, the client only gets the newest one
+        let flipbook = match package::from_json(&s, &entry.path().to_string_lossy()) {
             Ok(o) => o,
             Err(e) => {
                 tracing::error!(
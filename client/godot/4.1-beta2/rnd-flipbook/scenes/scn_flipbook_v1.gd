@@ -25,9 +25,9 @@ func _ready():
 func load_page(index: int) -> void:
 	print_debug("Loading page: %s" % index)
 	var img = self.fb_meta.get_image_on_page(index)
-	var texture : ImageTexture = ImageTexture.create_from_image(img)
 	var texture_rect = ($backdrop as TextureRect)
-	texture_rect.texture = texture
+	# Pages without a background are left blank
+	texture_rect.texture = ImageTexture.create_from_image(img) if img != null else null
 	
 	var asp : AudioStreamPlayer = ($AudioStreamPlayer as AudioStreamPlayer)
 	if asp.playing:
@@ -3,7 +3,7 @@ extends Object
 ## File Position in Package
 # As of
 # Format of bin entry
-#    "PAGE_34_Swedish": {
+#    "PAGE_34_sv": {
 #      "format": "mp3",
 #      "start": 29358559,
 #      "length": 10258
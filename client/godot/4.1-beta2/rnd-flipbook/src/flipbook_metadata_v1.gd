@@ -2,14 +2,18 @@ extends Object
 
 class_name FlipbookMetadataV1
 
+# Reads versions 1 and 2 of the metadata. Version 2 keeps a record per page, with the image, if
+#   any, and the string IDs of its texts and audio by language, fallbacks already applied
 var _from_remote: Dictionary
+var _version: int
 var _bin: PackedByteArray
 var img_miniature: Image
 
 func _init(from_remote: Dictionary, bin: PackedByteArray):
 	var version: int = from_remote["version"]
-	if version != 1:
-		push_error("Only version 1 is supported at the moment")
+	if version != 1 && version != 2:
+		push_error("Only versions 1 and 2 are supported at the moment")
+	self._version = version
 	self._from_remote = from_remote
 	self._bin = bin
 	self._construct_miniature()
@@ -29,13 +33,21 @@ func get_image_on_page(index: int) -> Image:
 	if fip == null:
 		return null
 	
-	var pba: PackedByteArray = self._bin.slice(fip.start, fip.start + fip.length - 1)
+	var pba: PackedByteArray = self._slice(fip)
 	var img: Image = Image.new()
 	match (fip.format):
 		"jpg":
 			if img.load_jpg_from_buffer(pba) != OK:
 				push_error("Error decoding JPG for image `%s`", index)
 				return null
+		"png":
+			if img.load_png_from_buffer(pba) != OK:
+				push_error("Error decoding PNG for image `%s`", index)
+				return null
+		"webp":
+			if img.load_webp_from_buffer(pba) != OK:
+				push_error("Error decoding WebP for image `%s`", index)
+				return null
 		_: 
 			push_error("Image format %s not supported", fip.format)
 			return null
@@ -46,31 +58,79 @@ func _image_fip(index: int) -> FilePositionInPackage:
 	if index < 0 || index >= self.get_page_count():
 		push_error("Image index requested out of range")
 		return null
-	return FilePositionInPackage.new(self._from_remote["images_in_pages"][index])
+	var raw = self._from_remote["images_in_pages"][index] if self._version == 1 else self._from_remote["pages"][index]["image"]
+	# Pages of version 2 may have no image at all
+	if raw == null:
+		return null
+	return FilePositionInPackage.new(raw)
 
 func _audio_fip(sid: String) -> FilePositionInPackage:
-	return FilePositionInPackage.new(self._from_remote["audio"][sid])
+	var raw = self._from_remote["audio"].get(sid)
+	if raw == null:
+		return null
+	return FilePositionInPackage.new(raw)
+
+# `slice` doesn't include its end
+func _slice(fip: FilePositionInPackage) -> PackedByteArray:
+	return self._bin.slice(fip.start, fip.start + fip.length)
 
-func get_audio_on_page(page: int, language: String) -> AudioStreamMP3:
+func get_audio_on_page(page: int, language: String) -> AudioStream:
 	var sid = "PAGE_%s_%s" % [page, language]
+	if self._version != 1:
+		sid = self._from_remote["pages"][page]["audio"].get(language, "")
 	print_debug("Looking for audio under SID: `%s`" % sid)
 	return self._internal_get_audio_on_page(sid)
 
-func _internal_get_audio_on_page(sid: String) -> AudioStreamMP3:
+func _internal_get_audio_on_page(sid: String) -> AudioStream:
 	var fip : FilePositionInPackage = self._audio_fip(sid)
 	if fip == null:
 		return null
-	var pba: PackedByteArray = self._bin.slice(fip.start, fip.start + fip.length - 1)
-	var mp3_audio: AudioStreamMP3 = AudioStreamMP3.new()
-	mp3_audio.data = pba
-	return mp3_audio
+	var pba: PackedByteArray = self._slice(fip)
+	match (fip.format):
+		"mp3":
+			var mp3_audio: AudioStreamMP3 = AudioStreamMP3.new()
+			mp3_audio.data = pba
+			return mp3_audio
+		"wav":
+			return self._wav_from_buffer(pba)
+		_:
+			push_error("Audio format %s not supported" % fip.format)
+			return null
+
+# Only the WAV `compile` writes when processing audio: 16 bit PCM
+func _wav_from_buffer(pba: PackedByteArray) -> AudioStreamWAV:
+	if pba.size() < 12 || pba.slice(0, 4).get_string_from_ascii() != "RIFF" || pba.slice(8, 12).get_string_from_ascii() != "WAVE":
+		push_error("Audio isn't a WAV file")
+		return null
+	var wav: AudioStreamWAV = AudioStreamWAV.new()
+	var pos: int = 12
+	while pos + 8 <= pba.size():
+		var chunk: String = pba.slice(pos, pos + 4).get_string_from_ascii()
+		var size: int = pba.decode_u32(pos + 4)
+		var body: int = pos + 8
+		if chunk == "fmt ":
+			if pba.decode_u16(body) != 1 || pba.decode_u16(body + 14) != 16:
+				push_error("Only 16 bit PCM WAV is supported")
+				return null
+			wav.format = AudioStreamWAV.FORMAT_16_BITS
+			wav.stereo = pba.decode_u16(body + 2) == 2
+			wav.mix_rate = pba.decode_u32(body + 4)
+		elif chunk == "data":
+			wav.data = pba.slice(body, body + size)
+			return wav
+		# Chunks are padded to an even length
+		pos = body + size + size % 2
+	push_error("WAV without samples")
+	return null
 
 ## Root level properties 
 func get_binary_package_url() -> String:
-	return self.from_remote["binary_package_url"]
+	return self._from_remote["binary_package_url"]
 
 func get_page_count() -> int:
-	return self._from_remote["images_in_pages"].size()
+	if self._version == 1:
+		return self._from_remote["images_in_pages"].size()
+	return self._from_remote["pages"].size()
 
 func get_default_language() -> String:
 	return self._from_remote["default_language"]
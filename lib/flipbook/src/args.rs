@@ -0,0 +1,243 @@
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    /// Print a machine readable report, as JSON, to stdout. Logs always go to stderr
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Validates and compiles a source flipbook
+    Compile(CompileArgs),
+    /// Compiles every source flipbook under a directory
+    Batch(BatchArgs),
+    /// Checks a source flipbook without compiling it
+    Validate {
+        /// Source flipbook, as JSON
+        source: String,
+
+        /// Directory the relative paths of the source are resolved against, by default the one
+        ///   holding the source
+        #[arg(long)]
+        asset_root: Option<String>,
+
+        /// Judge missing backgrounds as `compile` would with the same option
+        #[arg(long, value_enum, default_value_t = MissingBackgroundArg::Fail)]
+        missing_background: MissingBackgroundArg,
+
+        /// Judge assets whose content doesn't match their extension as `compile` would with the
+        ///   same option
+        #[arg(long)]
+        strict_formats: bool,
+    },
+    /// Describes a compiled flipbook: languages, pages and assets
+    Inspect {
+        /// Metadata of a compiled flipbook or a container (`.flipbook`)
+        input: String,
+    },
+    /// Writes a single asset of a compiled flipbook to a file
+    Extract {
+        /// Metadata of a compiled flipbook or a container (`.flipbook`)
+        input: String,
+
+        /// Page the asset belongs to
+        #[arg(short, long)]
+        page: usize,
+
+        /// Extract the audio in this language instead of the background image
+        #[arg(short, long)]
+        language: Option<String>,
+
+        /// File the asset is written to
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Recreates a source flipbook, and its assets, from a compiled one
+    Decompile {
+        /// Metadata of a compiled flipbook or a container (`.flipbook`)
+        input: String,
+
+        /// Directory where the assets and the source are written
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Compares two compiled flipbooks
+    Diff {
+        /// Metadata of a compiled flipbook or a container (`.flipbook`)
+        left: String,
+        /// Metadata of a compiled flipbook or a container (`.flipbook`)
+        right: String,
+    },
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct CompileArgs {
+    /// Source flipbook, as JSON
+    pub source: String,
+
+    /// Metadata file for the split format, the container itself otherwise
+    #[arg(short, long)]
+    pub output: String,
+
+    /// Binary package of the split format. By default next to the metadata, with `.bin` extension
+    #[arg(short, long)]
+    pub binary: Option<String>,
+
+    /// Directory the relative paths of the source are resolved against, by default the one
+    ///   holding the source
+    #[arg(long)]
+    pub asset_root: Option<String>,
+
+    #[command(flatten)]
+    pub flags: CompileFlags,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct BatchArgs {
+    /// Directory with the sources, every `*source.json` under it is compiled
+    pub root: String,
+
+    /// Directory the books and the catalogue index are written to
+    #[arg(short, long)]
+    pub output: String,
+
+    #[command(flatten)]
+    pub flags: CompileFlags,
+}
+
+/// Shared by every command that compiles
+#[derive(ClapArgs, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct CompileFlags {
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Split)]
+    pub format: OutputFormat,
+
+    #[arg(long, value_enum, default_value_t = MissingBackgroundArg::Fail)]
+    pub missing_background: MissingBackgroundArg,
+
+    /// Store every asset, even when it's identical to another one
+    #[arg(long)]
+    pub no_deduplicate: bool,
+
+    /// Compile even if the source has validation errors
+    #[arg(long)]
+    pub skip_validation: bool,
+
+    /// Compile everything again, even what's up to date
+    #[arg(long)]
+    pub force: bool,
+
+    /// Compile everything, without reading or writing a build manifest
+    #[arg(long, conflicts_with = "force")]
+    pub no_incremental: bool,
+
+    /// Fail on assets whose content doesn't match their extension, instead of warning
+    #[arg(long)]
+    pub strict_formats: bool,
+
+    /// Resize every background to `<width>x<height>`, encoding it again. Without it the
+    ///   backgrounds are packed as they are
+    #[arg(long, value_parser = parse_image_size)]
+    pub image_size: Option<(u32, u32)>,
+
+    /// Another resolution of every background, as `<name>=<width>x<height>`: `720p=1280x720`.
+    ///   Can be repeated
+    #[arg(long = "image-variant", value_parser = parse_image_variant)]
+    pub image_variants: Vec<(String, (u32, u32))>,
+
+    /// Write every image variant into a binary package of its own, `<binary>.<name>.bin`
+    #[arg(long)]
+    pub variant_packages: bool,
+
+    /// How the backgrounds, and their variants, are brought to their size
+    #[arg(long, value_enum, default_value_t = ResizeArg::Fit)]
+    pub image_resize: ResizeArg,
+
+    /// Format the backgrounds are encoded to with `--image-size`, and the variants
+    #[arg(long, value_enum, default_value_t = ImageFormatArg::Jpg)]
+    pub image_format: ImageFormatArg,
+
+    /// Quality of the JPEG backgrounds with `--image-size`, and the variants, 1 to 100
+    #[arg(long, default_value_t = 85, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub image_quality: u8,
+
+    /// Bring every audio to this RMS level, in dBFS: `-20`. Any of the `--audio-*` flags turns the
+    ///   audio stage on, every audio is decoded and measured
+    #[arg(long, allow_negative_numbers = true)]
+    pub audio_loudness: Option<f32>,
+
+    /// Trim the leading and trailing audio quieter than this, in dBFS: `-50`
+    #[arg(long, allow_negative_numbers = true)]
+    pub audio_trim: Option<f32>,
+
+    /// Format every audio is encoded to. Without it every audio keeps its format, trimming or
+    ///   normalizing anything but a WAV then fails: there's no encoder for the compressed ones
+    #[arg(long, value_enum)]
+    pub audio_format: Option<AudioFormatArg>,
+
+    /// Largest miniature embedded in the metadata, as `<width>x<height>`. By default `256x256`
+    #[arg(long, value_parser = parse_image_size)]
+    pub miniature_size: Option<(u32, u32)>,
+
+    /// Quality of the miniature, 1 to 100. By default 75
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub miniature_quality: Option<u8>,
+}
+
+fn parse_image_variant(variant: &str) -> Result<(String, (u32, u32)), String> {
+    let (name, size) = variant
+        .split_once('=')
+        .ok_or_else(|| format!("`{variant}` isn't `<name>=<width>x<height>`"))?;
+    Ok((name.to_string(), parse_image_size(size)?))
+}
+
+fn parse_image_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("`{size}` isn't `<width>x<height>`, like `1920x1080`");
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum OutputFormat {
+    /// Metadata (`.json`) and binary package (`.bin`)
+    Split,
+    /// A single `.flipbook` file
+    Container,
+}
+
+/// Mirrors `flipbook::compile::MissingBackground`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum MissingBackgroundArg {
+    Fail,
+    Placeholder,
+    LeaveEmpty,
+}
+
+/// Mirrors `flipbook::compile::ResizeMode`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ResizeArg {
+    Fit,
+    Fill,
+    Letterbox,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum ImageFormatArg {
+    Jpg,
+    Png,
+}
+
+/// Mirrors `flipbook::compile::AudioEncoding`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum AudioFormatArg {
+    Wav,
+}
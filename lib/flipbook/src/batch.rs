@@ -0,0 +1,363 @@
+// The `batch` module compiles a whole library at once: every source flipbook found under a root
+//   directory is validated and compiled, in parallel, into the same relative location under an
+//   output directory.
+// A book failing never stops the rest, every outcome ends up in the `CatalogueIndex` written next
+//   to the compiled books.
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::compile::{compile_container, compile_with_options, CompileOptions, CompileReport};
+use crate::container::CONTAINER_EXTENSION;
+use crate::error::{FlipbookError, Result};
+use crate::flipbook::common::FilePath;
+use crate::flipbook::source::FlipbookSource;
+use crate::validate::{validate_with_options, ValidationReport};
+
+/// Files whose name ends like this are taken as source flipbooks: `source.json`,
+///   `sample_source.json`, ..
+pub const SOURCE_SUFFIX: &str = "source.json";
+
+/// Name of the index written at the root of the output directory
+pub const CATALOGUE_INDEX_NAME: &str = "catalogue.json";
+
+#[derive(Clone, Debug, Default)]
+pub struct BatchOptions {
+    pub compile: CompileOptions,
+    /// Compile every book into a single file instead of metadata + binary package
+    pub container: bool,
+    /// Compile the books even if their sources have validation errors
+    pub skip_validation: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub enum BookOutcome {
+    Compiled(CompileReport),
+    /// The source has validation errors, it wasn't compiled
+    InvalidSource(ValidationReport),
+    Failed {
+        error: String,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BookResult {
+    pub source: FilePath,
+    /// Relative to the output directory: the metadata or the container
+    pub output: FilePath,
+    /// Relative to the output directory, `None` for containers
+    pub binary: Option<FilePath>,
+    pub outcome: BookOutcome,
+}
+
+impl BookResult {
+    pub fn is_compiled(&self) -> bool {
+        matches!(self.outcome, BookOutcome::Compiled(_))
+    }
+}
+
+/// Every book of a batch, in the order they were discovered
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CatalogueIndex {
+    pub books: Vec<BookResult>,
+}
+
+impl CatalogueIndex {
+    pub fn compiled(&self) -> impl Iterator<Item = &BookResult> {
+        self.books.iter().filter(|b| b.is_compiled())
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &BookResult> {
+        self.books.iter().filter(|b| !b.is_compiled())
+    }
+}
+
+/// Every source flipbook under `root`, sorted so batches are reproducible
+pub fn discover(root: &str) -> Result<Vec<PathBuf>> {
+    let mut sources = vec![];
+    for entry in walkdir::WalkDir::new(root).follow_links(true) {
+        let entry = entry.map_err(|e| FlipbookError::SourceRead {
+            path: root.to_string(),
+            source: e.into(),
+        })?;
+        let is_source = entry.file_type().is_file()
+            && entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.ends_with(SOURCE_SUFFIX));
+        if is_source {
+            sources.push(entry.into_path());
+        }
+    }
+    sources.sort();
+    Ok(sources)
+}
+
+/// Compiles every source under `root` into `output_dir`, writing the `CatalogueIndex` there as
+///   `CATALOGUE_INDEX_NAME`. Only failing to walk `root` or to write the index is an error, the
+///   books that can't be compiled are reported in the returned index
+pub fn compile_library(
+    root: &str,
+    output_dir: &str,
+    options: &BatchOptions,
+) -> Result<CatalogueIndex> {
+    let sources = discover(root)?;
+    tracing::info!("Found {} sources under `{}`", sources.len(), root);
+
+    let names = book_names(Path::new(root), &sources);
+    let books = sources
+        .par_iter()
+        .zip(&names)
+        .map(|(source, book)| {
+            // Both would write the same files at the same time
+            if names.iter().filter(|name| *name == book).count() > 1 {
+                return same_book(source, book, options);
+            }
+            compile_book(Path::new(output_dir), source, book, options)
+        })
+        .collect();
+    let index = CatalogueIndex { books };
+
+    let path_index = Path::new(output_dir).join(CATALOGUE_INDEX_NAME);
+    let output_error = |source| FlipbookError::OutputWrite {
+        path: path_index.to_string_lossy().to_string(),
+        source,
+    };
+    std::fs::create_dir_all(output_dir).map_err(output_error)?;
+    let f_index = std::fs::File::create(&path_index).map_err(output_error)?;
+    serde_json::to_writer_pretty(f_index, &index).map_err(|source| {
+        FlipbookError::MetadataSerialization {
+            path: path_index.to_string_lossy().to_string(),
+            source,
+        }
+    })?;
+
+    Ok(index)
+}
+
+/// `<root>/a/b/source.json` is the book `a/b`, a source right under the root is named after its
+///   file: `<root>/sample_source.json` is the book `sample_source`
+fn book_name(root: &Path, source: &Path) -> PathBuf {
+    let relative = source.strip_prefix(root).unwrap_or(source);
+    match relative.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => relative.with_extension(""),
+    }
+}
+
+/// The name of every book in `sources`, see `book_name`. Sources sharing a directory are named
+///   after their files instead: `<root>/a/source.json` and `<root>/a/other_source.json` are the
+///   books `a/source` and `a/other_source`
+fn book_names(root: &Path, sources: &[PathBuf]) -> Vec<PathBuf> {
+    let shares_directory = |source: &PathBuf| {
+        sources
+            .iter()
+            .filter(|s| s.parent() == source.parent())
+            .count()
+            > 1
+    };
+    sources
+        .iter()
+        .map(|source| {
+            if shares_directory(source) {
+                source
+                    .strip_prefix(root)
+                    .unwrap_or(source)
+                    .with_extension("")
+            } else {
+                book_name(root, source)
+            }
+        })
+        .collect()
+}
+
+fn with_extension(book: &Path, extension: &str) -> PathBuf {
+    let mut path = book.as_os_str().to_os_string();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+/// The book `a/b` goes to `a/b.json` + `a/b.bin`, or `a/b.flipbook`, in the output directory
+fn outputs(book: &Path, options: &BatchOptions) -> (PathBuf, Option<PathBuf>) {
+    if options.container {
+        (with_extension(book, CONTAINER_EXTENSION), None)
+    } else {
+        (
+            with_extension(book, "json"),
+            Some(with_extension(book, "bin")),
+        )
+    }
+}
+
+/// A source that isn't compiled, another one has the same book name
+fn same_book(source: &Path, book: &Path, options: &BatchOptions) -> BookResult {
+    let (output, binary) = outputs(book, options);
+    let error = format!("another source is the book `{}` too", book.display());
+    tracing::error!("Failed `{}`: {}", source.display(), error);
+    BookResult {
+        source: source.to_string_lossy().to_string(),
+        output: output.to_string_lossy().to_string(),
+        binary: binary.map(|b| b.to_string_lossy().to_string()),
+        outcome: BookOutcome::Failed { error },
+    }
+}
+
+fn compile_book(
+    output_dir: &Path,
+    source: &Path,
+    book: &Path,
+    options: &BatchOptions,
+) -> BookResult {
+    let (output, binary) = outputs(book, options);
+
+    let outcome = match try_compile_book(source, output_dir, &output, binary.as_deref(), options) {
+        Ok(outcome) => outcome,
+        Err(e) => BookOutcome::Failed {
+            error: error_chain(&e),
+        },
+    };
+    match &outcome {
+        BookOutcome::Compiled(report) => {
+            tracing::info!("{:?} `{}`", report.status, source.display());
+        }
+        BookOutcome::InvalidSource(_) => tracing::error!("Invalid `{}`", source.display()),
+        BookOutcome::Failed { error } => {
+            tracing::error!("Failed `{}`: {}", source.display(), error)
+        }
+    }
+
+    BookResult {
+        source: source.to_string_lossy().to_string(),
+        output: output.to_string_lossy().to_string(),
+        binary: binary.map(|b| b.to_string_lossy().to_string()),
+        outcome,
+    }
+}
+
+fn try_compile_book(
+    path_source: &Path,
+    output_dir: &Path,
+    output: &Path,
+    binary: Option<&Path>,
+    options: &BatchOptions,
+) -> Result<BookOutcome> {
+    let source = FlipbookSource::from_file(&path_source.to_string_lossy())?;
+    let validation = validate_with_options(&source, &options.compile);
+    if validation.has_errors() && !options.skip_validation {
+        return Ok(BookOutcome::InvalidSource(validation));
+    }
+
+    let path_output = output_dir.join(output);
+    if let Some(parent) = path_output.parent() {
+        std::fs::create_dir_all(parent).map_err(|source| FlipbookError::OutputWrite {
+            path: parent.to_string_lossy().to_string(),
+            source,
+        })?;
+    }
+    let path_output = path_output.to_string_lossy();
+
+    let report = match binary {
+        Some(binary) => {
+            let path_binary = output_dir.join(binary);
+            compile_with_options(
+                &source,
+                &path_output,
+                &path_binary.to_string_lossy(),
+                &options.compile,
+            )?
+        }
+        None => compile_container(&source, &path_output, &options.compile)?,
+    };
+    Ok(BookOutcome::Compiled(report))
+}
+
+/// The error and everything that caused it, as a single line
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(s) = source {
+        chain.push_str(&format!(": {s}"));
+        source = s.source();
+    }
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{sample_source, scratch_dir};
+
+    #[test]
+    fn compiles_a_library_reporting_every_book() {
+        let dir = scratch_dir("batch");
+        let root = dir.join("library");
+        let output = dir.join("output");
+
+        std::fs::write(dir.join("01.jpg"), "image 01").unwrap();
+        let source = sample_source(&dir);
+        for book in ["a", "b/c"] {
+            std::fs::create_dir_all(root.join(book)).unwrap();
+            let f = std::fs::File::create(root.join(book).join("source.json")).unwrap();
+            serde_json::to_writer(f, &source).unwrap();
+        }
+        std::fs::create_dir_all(root.join("broken")).unwrap();
+        std::fs::write(root.join("broken/source.json"), "{").unwrap();
+        std::fs::write(root.join("notes.json"), "{}").unwrap();
+
+        let index = compile_library(
+            root.to_str().unwrap(),
+            output.to_str().unwrap(),
+            &BatchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(index.books.len(), 3);
+        assert_eq!(index.compiled().count(), 2);
+        let failed: Vec<_> = index.failed().collect();
+        assert!(failed[0].source.ends_with("broken/source.json"));
+        assert!(matches!(failed[0].outcome, BookOutcome::Failed { .. }));
+
+        assert!(output.join("a.json").is_file());
+        assert!(output.join("b/c.bin").is_file());
+        assert!(output.join(CATALOGUE_INDEX_NAME).is_file());
+    }
+
+    #[test]
+    fn books_with_the_same_name() {
+        let dir = scratch_dir("batch-names");
+        let root = dir.join("library");
+        let output = dir.join("output");
+
+        std::fs::write(dir.join("01.jpg"), "image 01").unwrap();
+        let source = sample_source(&dir);
+        for path in [
+            "a/source.json",
+            "a/other_source.json",
+            "a/source/source.json",
+            "b/source.json",
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let f = std::fs::File::create(path).unwrap();
+            serde_json::to_writer(f, &source).unwrap();
+        }
+
+        let index = compile_library(
+            root.to_str().unwrap(),
+            output.to_str().unwrap(),
+            &BatchOptions::default(),
+        )
+        .unwrap();
+
+        // Sharing a directory they're named after their files, `a/source` is then two books
+        assert_eq!(index.compiled().count(), 2);
+        assert!(output.join("a/other_source.json").is_file());
+        assert!(output.join("b.json").is_file());
+        let failed: Vec<_> = index.failed().collect();
+        assert_eq!(failed.len(), 2);
+        assert!(failed.iter().all(|b| b.output == "a/source.json"));
+        assert!(!output.join("a/source.json").exists());
+    }
+}
@@ -0,0 +1,453 @@
+// What each subcommand of the CLI does. Every command logs through `tracing` and, with `--json`,
+//   prints a report to stdout for the build scripts to consume.
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use flipbook::batch::{self, BatchOptions};
+use flipbook::compile::{
+    self, AudioEncoding, AudioOptions, CompileOptions, CompileReport, ImageEncoding, ImageOptions,
+    ImageVariant, MiniatureOptions, MissingBackground, ResizeMode,
+};
+use flipbook::container::CONTAINER_EXTENSION;
+use flipbook::diff;
+use flipbook::flipbook::common::{LanguageCode, MetadataVersion, RawString};
+use flipbook::flipbook::language::LanguageTag;
+use flipbook::flipbook::source::FlipbookSource;
+use flipbook::reader::PackageReader;
+use flipbook::validate::{self, ValidationReport};
+
+use crate::args::{
+    AudioFormatArg, BatchArgs, CompileArgs, CompileFlags, ImageFormatArg, MissingBackgroundArg,
+    OutputFormat, ResizeArg,
+};
+
+fn print_json<T: Serialize>(report: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(report)?);
+    Ok(())
+}
+
+/// Compiled flipbooks are given either as their metadata or as a container
+fn open_input(input: &str) -> Result<PackageReader> {
+    let is_container = Path::new(input)
+        .extension()
+        .is_some_and(|e| e == CONTAINER_EXTENSION);
+    let reader = if is_container {
+        PackageReader::open_container(input)?
+    } else {
+        PackageReader::open_metadata(input)?
+    };
+    Ok(reader)
+}
+
+fn load_source(path: &str, asset_root: Option<&str>) -> Result<FlipbookSource> {
+    let source = match asset_root {
+        Some(root) => FlipbookSource::from_file_with_asset_root(path, root)?,
+        None => FlipbookSource::from_file(path)?,
+    };
+    Ok(source)
+}
+
+fn log_validation(report: &ValidationReport) {
+    for issue in report.warnings() {
+        tracing::warn!("{}", issue);
+    }
+    for issue in report.errors() {
+        tracing::error!("{}", issue);
+    }
+    for (lang, coverage) in &report.coverage {
+        tracing::info!(
+            "Language `{}`: {} texts of its own, {} from fallbacks, {} missing",
+            lang,
+            coverage.native,
+            coverage.fallback,
+            coverage.missing
+        );
+    }
+}
+
+#[derive(Serialize)]
+struct CompileOutput {
+    validation: ValidationReport,
+    /// `None` when the source didn't pass validation
+    compile: Option<CompileReport>,
+}
+
+impl From<MissingBackgroundArg> for MissingBackground {
+    fn from(arg: MissingBackgroundArg) -> Self {
+        match arg {
+            MissingBackgroundArg::Fail => Self::Fail,
+            MissingBackgroundArg::Placeholder => Self::Placeholder,
+            MissingBackgroundArg::LeaveEmpty => Self::LeaveEmpty,
+        }
+    }
+}
+
+fn compile_options(flags: &CompileFlags) -> CompileOptions {
+    CompileOptions {
+        missing_background: flags.missing_background.into(),
+        deduplicate_assets: !flags.no_deduplicate,
+        incremental: !flags.no_incremental,
+        force: flags.force,
+        strict_formats: flags.strict_formats,
+        images: flags.image_size.map(|size| image_options(flags, size)),
+        miniature: miniature_options(flags),
+        image_variants: flags
+            .image_variants
+            .iter()
+            .map(|(name, size)| ImageVariant {
+                name: name.clone(),
+                image: image_options(flags, *size),
+            })
+            .collect(),
+        variant_packages: flags.variant_packages,
+        audio: audio_options(flags),
+    }
+}
+
+/// The audio stage only runs when asked for, and then only does what it was asked for
+const fn audio_options(flags: &CompileFlags) -> Option<AudioOptions> {
+    if flags.audio_loudness.is_none() && flags.audio_trim.is_none() && flags.audio_format.is_none()
+    {
+        return None;
+    }
+    Some(AudioOptions {
+        target_loudness_db: flags.audio_loudness,
+        trim_silence_db: flags.audio_trim,
+        encoding: match flags.audio_format {
+            Some(AudioFormatArg::Wav) => Some(AudioEncoding::Wav),
+            None => None,
+        },
+    })
+}
+
+fn miniature_options(flags: &CompileFlags) -> MiniatureOptions {
+    let default = MiniatureOptions::default();
+    let (max_width, max_height) = flags
+        .miniature_size
+        .unwrap_or((default.max_width, default.max_height));
+    MiniatureOptions {
+        max_width,
+        max_height,
+        quality: flags.miniature_quality.unwrap_or(default.quality),
+    }
+}
+
+/// Backgrounds and their variants share everything but the size
+const fn image_options(flags: &CompileFlags, (width, height): (u32, u32)) -> ImageOptions {
+    ImageOptions {
+        width,
+        height,
+        resize: match flags.image_resize {
+            ResizeArg::Fit => ResizeMode::Fit,
+            ResizeArg::Fill => ResizeMode::Fill,
+            ResizeArg::Letterbox => ResizeMode::Letterbox,
+        },
+        encoding: match flags.image_format {
+            ImageFormatArg::Jpg => ImageEncoding::Jpg {
+                quality: flags.image_quality,
+            },
+            ImageFormatArg::Png => ImageEncoding::Png,
+        },
+        letterbox_color: [0, 0, 0],
+    }
+}
+
+pub fn compile(args: &CompileArgs, json: bool) -> Result<()> {
+    tracing::info!("Reading from: {}", args.source);
+    let source = load_source(&args.source, args.asset_root.as_deref())?;
+
+    let options = compile_options(&args.flags);
+    let validation = validate::validate_with_options(&source, &options);
+    log_validation(&validation);
+    if validation.has_errors() && !args.flags.skip_validation {
+        if json {
+            print_json(&CompileOutput {
+                validation,
+                compile: None,
+            })?;
+        }
+        anyhow::bail!("Source `{}` didn't pass validation", args.source);
+    }
+
+    let report = match args.flags.format {
+        OutputFormat::Split => {
+            let path_binary = args.binary.clone().unwrap_or_else(|| {
+                Path::new(&args.output)
+                    .with_extension("bin")
+                    .to_string_lossy()
+                    .to_string()
+            });
+            let report =
+                compile::compile_with_options(&source, &args.output, &path_binary, &options)?;
+            tracing::info!("Metadata generated: {}", args.output);
+            tracing::info!("Binary generated: {}", path_binary);
+            report
+        }
+        OutputFormat::Container => {
+            let report = compile::compile_container(&source, &args.output, &options)?;
+            tracing::info!("Container generated: {}", args.output);
+            report
+        }
+    };
+    tracing::info!(
+        "{:?}: {} bytes of assets, {} saved reusing {} assets. Miniature: {} bytes",
+        report.status,
+        report.binary_package_length,
+        report.bytes_saved,
+        report.reused_assets,
+        report.miniature_length
+    );
+
+    if json {
+        print_json(&CompileOutput {
+            validation,
+            compile: Some(report),
+        })?;
+    }
+    Ok(())
+}
+
+pub fn batch(args: &BatchArgs, json: bool) -> Result<()> {
+    let options = BatchOptions {
+        compile: compile_options(&args.flags),
+        container: matches!(args.flags.format, OutputFormat::Container),
+        skip_validation: args.flags.skip_validation,
+    };
+    let index = batch::compile_library(&args.root, &args.output, &options)?;
+
+    let failed = index.failed().count();
+    tracing::info!(
+        "{} books compiled, {} failed. Index: {}/{}",
+        index.compiled().count(),
+        failed,
+        args.output,
+        batch::CATALOGUE_INDEX_NAME
+    );
+    if json {
+        print_json(&index)?;
+    }
+    if failed > 0 {
+        anyhow::bail!(
+            "{} books under `{}` couldn't be compiled",
+            failed,
+            args.root
+        );
+    }
+    Ok(())
+}
+
+pub fn validate(
+    path: &str,
+    asset_root: Option<&str>,
+    missing_background: MissingBackgroundArg,
+    strict_formats: bool,
+    json: bool,
+) -> Result<()> {
+    let source = load_source(path, asset_root)?;
+    let options = CompileOptions {
+        missing_background: missing_background.into(),
+        strict_formats,
+        ..Default::default()
+    };
+    let report = validate::validate_with_options(&source, &options);
+    log_validation(&report);
+    if json {
+        print_json(&report)?;
+    }
+    if report.has_errors() {
+        anyhow::bail!("Source `{}` didn't pass validation", path);
+    }
+    tracing::info!("Source `{}` is valid", path);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct PageInspection {
+    /// Format and length of the background
+    image: Option<(String, u64)>,
+    /// Names of the other resolutions of the background
+    variants: Vec<String>,
+    texts: Vec<LanguageCode>,
+    audio: Vec<LanguageCode>,
+    /// Languages whose text comes with timing tracks
+    timings: Vec<LanguageCode>,
+    /// Text blocks drawn at a fixed place of the page
+    blocks: usize,
+}
+
+#[derive(Serialize)]
+struct Inspection {
+    version: MetadataVersion,
+    languages: Vec<LanguageCode>,
+    /// Every language in itself: `sv` is `svenska`
+    language_names: BTreeMap<LanguageCode, String>,
+    default_language: LanguageCode,
+    title: Option<RawString>,
+    assets_length: u64,
+    audio_assets: usize,
+    pages: Vec<PageInspection>,
+}
+
+/// Packages compiled before the tags were canonical may have anything as a language
+fn language_name(lang: &str) -> String {
+    LanguageTag::parse_lenient(lang).map_or_else(|_| lang.to_string(), |tag| tag.display_name(lang))
+}
+
+pub fn inspect(input: &str, json: bool) -> Result<()> {
+    let reader = open_input(input)?;
+    let package = reader.package();
+
+    let pages: Vec<PageInspection> = package
+        .pages
+        .iter()
+        .map(|p| PageInspection {
+            image: p.image.as_ref().map(|i| (i.format.to_string(), i.length)),
+            variants: p.variants.keys().cloned().collect(),
+            texts: p.texts.keys().cloned().collect(),
+            audio: p.audio.keys().cloned().collect(),
+            timings: p
+                .texts
+                .iter()
+                .filter(|(_, sid)| package.timings.contains_key(*sid))
+                .map(|(lang, _)| lang.clone())
+                .collect(),
+            blocks: p.blocks.len(),
+        })
+        .collect();
+    let inspection = Inspection {
+        version: package.version,
+        languages: package.languages.clone(),
+        language_names: package
+            .languages
+            .iter()
+            .map(|lang| (lang.clone(), language_name(lang)))
+            .collect(),
+        default_language: package.default_language.clone(),
+        title: reader.title(reader.default_language()).cloned(),
+        assets_length: reader.assets_length(),
+        audio_assets: package.audio.len(),
+        pages,
+    };
+
+    if json {
+        return print_json(&inspection);
+    }
+
+    println!("{input}");
+    println!("  version: {}", inspection.version);
+    let languages: Vec<String> = inspection
+        .languages
+        .iter()
+        .map(|lang| format!("{lang} {}", inspection.language_names[lang]))
+        .collect();
+    println!(
+        "  languages: {} (default `{}`)",
+        languages.join(", "),
+        inspection.default_language
+    );
+    if let Some(title) = &inspection.title {
+        println!("  title: {title}");
+    }
+    println!(
+        "  assets: {} bytes, {} audio",
+        inspection.assets_length, inspection.audio_assets
+    );
+    for (pos, page) in inspection.pages.iter().enumerate() {
+        let image = page.image.as_ref().map_or_else(
+            || "no image".to_string(),
+            |(format, length)| format!("{format} {length} bytes"),
+        );
+        println!(
+            "  page {pos}: {image}, texts {:?}, audio {:?}, timings {:?}, blocks {}",
+            page.texts, page.audio, page.timings, page.blocks
+        );
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Extraction {
+    format: String,
+    length: usize,
+    output: String,
+}
+
+pub fn extract(
+    input: &str,
+    page: usize,
+    language: Option<&str>,
+    output: &str,
+    json: bool,
+) -> Result<()> {
+    let mut reader = open_input(input)?;
+    let asset = match language {
+        Some(lang) => reader.audio(page, lang)?,
+        None => reader.image(page)?,
+    };
+    let Some(asset) = asset else {
+        anyhow::bail!("Page {} has no such asset in `{}`", page, input);
+    };
+
+    std::fs::write(output, &asset.content)?;
+    tracing::info!(
+        "Extracted {} bytes of {} into {}",
+        asset.content.len(),
+        asset.format,
+        output
+    );
+    if json {
+        print_json(&Extraction {
+            format: asset.format.to_string(),
+            length: asset.content.len(),
+            output: output.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+pub fn decompile(input: &str, output: &str, json: bool) -> Result<()> {
+    let mut reader = open_input(input)?;
+    let source = compile::decompile(&mut reader, output)?;
+    tracing::info!(
+        "Source generated: {}/{} ({} pages)",
+        output,
+        compile::DECOMPILED_SOURCE_NAME,
+        source.pages.len()
+    );
+    // See `compile::decompile`, they point at a file that doesn't exist
+    let without_background: Vec<String> = (source.pages.iter().enumerate())
+        .filter(|(_, page)| !Path::new(&source.resolve(&page.background.path)).is_file())
+        .map(|(pos, _)| pos.to_string())
+        .collect();
+    if !without_background.is_empty() {
+        tracing::warn!(
+            "Pages without background: {}. Compile the source with `--missing-background \
+             leave-empty`",
+            without_background.join(", ")
+        );
+    }
+    if json {
+        print_json(&source)?;
+    }
+    Ok(())
+}
+
+pub fn diff(left: &str, right: &str, json: bool) -> Result<()> {
+    let mut left_reader = open_input(left)?;
+    let mut right_reader = open_input(right)?;
+    let report = diff::diff(&mut left_reader, &mut right_reader)?;
+
+    if json {
+        return print_json(&report);
+    }
+    if report.is_empty() {
+        println!("`{left}` and `{right}` are the same flipbook");
+    }
+    for difference in &report.differences {
+        println!("{difference}");
+    }
+    Ok(())
+}
@@ -1,26 +1,242 @@
-use anyhow::Result;
-
+mod audio;
 mod build;
 mod common;
+mod decompile;
+mod images;
+mod incremental;
+mod options;
 mod persistence;
+mod report;
+mod writer;
 
+use crate::error::Result;
 use crate::flipbook::source::FlipbookSource;
 
-use common::Arguments;
+use common::{Arguments, Destination};
+
+pub(crate) use audio::duration_ms as audio_duration_ms;
+pub use decompile::{decompile, DECOMPILED_SOURCE_NAME};
+pub use incremental::MANIFEST_EXTENSION;
+pub use options::{
+    AudioEncoding, AudioOptions, CompileOptions, ImageEncoding, ImageOptions, ImageVariant,
+    MiniatureOptions, MissingBackground, ResizeMode,
+};
+pub use report::{BuildStatus, CompileReport};
+pub(crate) use writer::to_digest;
 
 /// Transform a source flipbook into an artifact consumable by the client and possible to serve.
 /// The compiled (or packaged) version of a flipbook is a combination of 2 artifacts
 ///   1. Metadata
 ///   2. A binary blob
-/// Ideally the source flipbook has been sanitized by doing sanity checks such as:
-///    the file exists and the expected texts are there
-pub fn compile(source: &FlipbookSource, path_metadata: &str, path_binary: &str) -> Result<()> {
-    let args = Arguments {
+///
+/// `compile_container` packs both into a single file instead
+///
+/// Ideally the source flipbook has been sanitized through `crate::validate::validate` first:
+///   the files exist and the expected texts are there. Its languages are compiled as their
+///   canonical tags, see `FlipbookSource::with_canonical_languages`
+pub fn compile(
+    source: &FlipbookSource,
+    path_metadata: &str,
+    path_binary: &str,
+) -> Result<CompileReport> {
+    compile_with_options(
         source,
         path_metadata,
         path_binary,
+        &CompileOptions::default(),
+    )
+}
+
+/// Same as `compile` but tuning how the process behaves through `CompileOptions`
+pub fn compile_with_options(
+    source: &FlipbookSource,
+    path_metadata: &str,
+    path_binary: &str,
+    options: &CompileOptions,
+) -> Result<CompileReport> {
+    let source = &source.with_canonical_languages()?;
+    let args = Arguments {
+        source,
+        destination: Destination::Split {
+            path_metadata,
+            path_binary,
+        },
+        options,
+    };
+    run(&args)
+}
+
+/// Same as `compile_with_options` but producing a single file, see `crate::container`
+pub fn compile_container(
+    source: &FlipbookSource,
+    path_container: &str,
+    options: &CompileOptions,
+) -> Result<CompileReport> {
+    let source = &source.with_canonical_languages()?;
+    let args = Arguments {
+        source,
+        destination: Destination::Container {
+            path: path_container,
+        },
+        options,
+    };
+    run(&args)
+}
+
+fn run(args: &Arguments) -> Result<CompileReport> {
+    if !args.options.incremental {
+        let cr = build::build(args)?;
+        persistence::to_disk(&cr, args)?;
+        // A manifest left by an earlier build no longer describes these outputs
+        let _ = std::fs::remove_file(incremental::manifest_path(args));
+        return Ok(cr.report);
+    }
+
+    let path_manifest = incremental::manifest_path(args);
+    let previous = if args.options.force {
+        None
+    } else {
+        incremental::load_manifest(&path_manifest)
+    };
+    let status = match &previous {
+        Some(previous) => incremental::plan(args, previous)?,
+        None => BuildStatus::Rebuilt,
     };
 
-    let cr = build::build(&args)?;
-    persistence::to_disk(&cr, path_metadata, path_binary)
+    let cr = match (status, previous) {
+        (BuildStatus::UpToDate, Some(previous)) => {
+            return Ok(CompileReport {
+                status,
+                ..previous.report()
+            });
+        }
+        (BuildStatus::MetadataOnly, Some(previous)) => {
+            let report = CompileReport {
+                status,
+                ..previous.report()
+            };
+            let metadata = incremental::previous_metadata(args)?;
+            build::rebuild_metadata(args, &metadata, report)?
+        }
+        _ => build::build(args)?,
+    };
+    persistence::to_disk(&cr, args)?;
+    incremental::save_manifest(&path_manifest, &incremental::manifest_for(args, &cr)?)?;
+    Ok(cr.report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::FlipbookError;
+    use crate::flipbook::format::BinaryFormat;
+    use crate::flipbook::source::{Image, SourcePage};
+    use crate::test_utils::{
+        compile_sample, compile_source, path_binary, sample_options, sample_source, scratch_dir,
+        slice,
+    };
+
+    #[test]
+    fn missing_background_fails_by_default() {
+        let dir = scratch_dir("fail");
+        let result = compile_source(&dir, &sample_source(&dir), &CompileOptions::default());
+        assert!(matches!(
+            result,
+            Err(FlipbookError::AssetMissing { context }) if context.page == Some(1)
+        ));
+    }
+
+    #[test]
+    fn missing_background_keeps_pages_aligned() {
+        let dir = scratch_dir("leave-empty");
+        let reader = compile_sample(&dir, &sample_options());
+        let package = reader.package();
+        let binary = std::fs::read(path_binary(&dir)).unwrap();
+
+        assert_eq!(package.pages.len(), 3);
+        assert!(package.pages[1].image.is_none());
+        assert_eq!(package.pages[1].texts["en"], "PAGE_1_en");
+
+        let page_2 = &package.pages[2];
+        assert_eq!(slice(&binary, page_2.image.as_ref().unwrap()), b"image 02");
+        assert_eq!(package.texts[&page_2.texts["en"]], "two");
+        assert_eq!(
+            slice(&binary, &package.audio[&page_2.audio["en"]]),
+            b"audio 02"
+        );
+    }
+
+    #[test]
+    fn missing_background_placeholder() {
+        let dir = scratch_dir("placeholder");
+        let options = CompileOptions {
+            missing_background: MissingBackground::Placeholder,
+            ..Default::default()
+        };
+        let reader = compile_sample(&dir, &options);
+        let package = reader.package();
+        let binary = std::fs::read(path_binary(&dir)).unwrap();
+
+        let placeholder = package.pages[1].image.as_ref().unwrap();
+        assert_eq!(placeholder.format, BinaryFormat::Jpg);
+        assert_eq!(slice(&binary, placeholder), build::PLACEHOLDER_IMAGE);
+        assert!(package.pages.iter().all(|p| p.image.is_some()));
+    }
+
+    #[test]
+    fn identical_assets_are_stored_once() {
+        let dir = scratch_dir("dedup");
+        std::fs::write(dir.join("copy_of_02.jpg"), "image 02").unwrap();
+
+        // Page 1 reuses the file of page 0, page 2 gets a copy of its own background
+        let mut source = sample_source(&dir);
+        source.pages[1].background = source.pages[0].background.clone();
+        source.pages[2].background.path = dir.join("copy_of_02.jpg").to_str().unwrap().to_string();
+        source.pages.push(SourcePage {
+            background: Image {
+                path: dir.join("02.jpg").to_str().unwrap().to_string(),
+            },
+            text: None,
+            blocks: vec![],
+        });
+
+        let path_metadata = dir.join("out.json");
+        let report = compile(&source, path_metadata.to_str().unwrap(), &path_binary(&dir)).unwrap();
+
+        let binary = std::fs::read(path_binary(&dir)).unwrap();
+        assert_eq!(report.reused_assets, 2);
+        assert_eq!(report.bytes_saved, 16);
+        assert_eq!(binary.len() as u64, report.binary_package_length);
+        assert_eq!(binary, b"image 00image 02audio 02");
+    }
+
+    #[test]
+    fn same_source_same_artifacts() {
+        let dir = scratch_dir("deterministic");
+        let mut source = sample_source(&dir);
+        source.languages = vec!["en".into(), "sv".into(), "es".into(), "de".into()];
+        let text = source.pages[2].text.as_mut().unwrap();
+        for lang in ["sv", "es", "de"] {
+            let path = dir.join(format!("02_{lang}.ogg"));
+            std::fs::write(&path, format!("audio 02 {lang}")).unwrap();
+            let mut asset = text.0["en"].clone();
+            asset.audio.as_mut().unwrap().path = path.to_str().unwrap().to_string();
+            text.0.insert(lang.to_string(), asset);
+        }
+
+        let mut artifacts = vec![];
+        for run in ["first", "second"] {
+            let out = dir.join(run);
+            std::fs::create_dir_all(&out).unwrap();
+            compile_source(&out, &source, &sample_options()).unwrap();
+            artifacts.push((
+                std::fs::read(out.join("out.json")).unwrap(),
+                std::fs::read(out.join("out.bin")).unwrap(),
+            ));
+        }
+        assert_eq!(artifacts[0], artifacts[1]);
+        assert!(artifacts[0]
+            .1
+            .ends_with(b"audio 02 deaudio 02audio 02 esaudio 02 sv"));
+    }
 }
@@ -0,0 +1,369 @@
+// The audio stage of the compilation. Narrations are recorded by different people, with different
+//   gear, and arrive in whatever format the recording software had by default: here they're
+//   decoded, the silence around them is trimmed and they're brought to the same loudness, so
+//   turning a page never surprises the reader with a much louder or quieter voice.
+// Everything is pure Rust: symphonia decodes and hound writes the WAV.
+use std::io::Cursor;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::error::{AssetContext, FlipbookError, Result};
+use crate::flipbook::format::BinaryFormat;
+use crate::flipbook::package::AudioProperties;
+
+use super::options::{AudioEncoding, AudioOptions};
+
+/// Normalizing never takes the peaks over this, in dBFS
+const PEAK_CEILING_DB: f32 = -1.0;
+
+/// An audio ready to be packed
+pub struct ProcessedAudio {
+    pub format: BinaryFormat,
+    pub content: Vec<u8>,
+    pub properties: AudioProperties,
+}
+
+/// Interleaved samples, from -1.0 to 1.0
+struct Decoded {
+    samples: Vec<f32>,
+    channels: usize,
+    sample_rate: u32,
+}
+
+impl Decoded {
+    fn frames(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    fn properties(&self) -> AudioProperties {
+        AudioProperties {
+            duration_ms: self.frames() as u64 * 1000 / u64::from(self.sample_rate.max(1)),
+            sample_rate: self.sample_rate,
+            channels: self.channels as u16,
+        }
+    }
+}
+
+/// Decodes `content`, in `format`, and encodes it again as `options` says
+pub fn process(
+    content: Vec<u8>,
+    format: BinaryFormat,
+    options: &AudioOptions,
+    context: &AssetContext,
+) -> Result<ProcessedAudio> {
+    let processing_error =
+        |source: Box<dyn std::error::Error + Send + Sync>| FlipbookError::AudioProcessing {
+            context: context.clone(),
+            source,
+        };
+
+    let untouched = options.trim_silence_db.is_none() && options.target_loudness_db.is_none();
+    let encoding = match (options.encoding, format) {
+        (Some(encoding), _) => encoding,
+        (None, BinaryFormat::Wav) => AudioEncoding::Wav,
+        // Only measured, the content is packed as it came
+        (None, _) if untouched => {
+            let decoded =
+                decode(content.clone(), format).map_err(|e| processing_error(e.into()))?;
+            return Ok(ProcessedAudio {
+                format,
+                content,
+                properties: decoded.properties(),
+            });
+        }
+        (None, _) => {
+            return Err(FlipbookError::AudioEncodingUnavailable {
+                context: context.clone(),
+                format,
+            })
+        }
+    };
+
+    let mut decoded = decode(content, format).map_err(|e| processing_error(e.into()))?;
+    if let Some(threshold) = options.trim_silence_db {
+        trim_silence(&mut decoded, threshold);
+    }
+    if let Some(target) = options.target_loudness_db {
+        normalize(&mut decoded, target);
+    }
+
+    let content = match encoding {
+        AudioEncoding::Wav => encode_wav(&decoded).map_err(|e| processing_error(e.into()))?,
+    };
+    Ok(ProcessedAudio {
+        format: BinaryFormat::Wav,
+        content,
+        properties: decoded.properties(),
+    })
+}
+
+/// How long `content`, in `format`, plays. `None` when it can't be decoded
+pub fn duration_ms(content: Vec<u8>, format: BinaryFormat) -> Option<u64> {
+    decode(content, format)
+        .ok()
+        .map(|decoded| decoded.properties().duration_ms)
+}
+
+fn decode(content: Vec<u8>, format: BinaryFormat) -> std::result::Result<Decoded, DecodeError> {
+    let stream = MediaSourceStream::new(Box::new(Cursor::new(content)), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(format.as_str());
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut reader = probed.format;
+    let track = reader
+        .default_track()
+        .ok_or(DecodeError::Unsupported("no audio track"))?;
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut decoded = Decoded {
+        samples: vec![],
+        channels: track.codec_params.channels.map_or(1, |c| c.count()),
+        sample_rate: track.codec_params.sample_rate.unwrap_or_default(),
+    };
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            // The way every stream ends
+            Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let buffer = match decoder.decode(&packet) {
+            Ok(buffer) => buffer,
+            // A corrupted packet is skipped, the rest of the audio is still good
+            Err(DecodeError::DecodeError(e)) => {
+                tracing::warn!("Skipping a packet that can't be decoded: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let spec = *buffer.spec();
+        decoded.channels = spec.channels.count();
+        decoded.sample_rate = spec.rate;
+        let mut samples = SampleBuffer::<f32>::new(buffer.capacity() as u64, spec);
+        samples.copy_interleaved_ref(buffer);
+        decoded.samples.extend_from_slice(samples.samples());
+    }
+    // Frames are counted, trimmed and encoded by channel
+    if decoded.channels == 0 {
+        return Err(DecodeError::Unsupported("an audio without channels"));
+    }
+    Ok(decoded)
+}
+
+fn to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Removes the frames before the first and after the last one louder than `threshold_db`. An
+///   audio that's all silence is left as it is
+fn trim_silence(decoded: &mut Decoded, threshold_db: f32) {
+    let threshold = to_linear(threshold_db);
+    let channels = decoded.channels;
+    let is_loud = |frame: &[f32]| frame.iter().any(|s| s.abs() > threshold);
+    let frames: Vec<&[f32]> = decoded.samples.chunks(channels).collect();
+    let (Some(first), Some(last)) = (
+        frames.iter().position(|f| is_loud(f)),
+        frames.iter().rposition(|f| is_loud(f)),
+    ) else {
+        return;
+    };
+    decoded.samples = decoded.samples[first * channels..(last + 1) * channels].to_vec();
+}
+
+/// Brings the RMS level to `target_db`, as long as the peaks stay under `PEAK_CEILING_DB`
+fn normalize(decoded: &mut Decoded, target_db: f32) {
+    if decoded.samples.is_empty() {
+        return;
+    }
+    let squares: f64 = decoded.samples.iter().map(|s| f64::from(s * s)).sum();
+    let rms = (squares / decoded.samples.len() as f64).sqrt() as f32;
+    let peak = decoded
+        .samples
+        .iter()
+        .fold(0f32, |peak, s| peak.max(s.abs()));
+    if rms == 0.0 {
+        return;
+    }
+    let gain = (to_linear(target_db) / rms).min(to_linear(PEAK_CEILING_DB) / peak);
+    for sample in &mut decoded.samples {
+        *sample *= gain;
+    }
+}
+
+fn encode_wav(decoded: &Decoded) -> std::result::Result<Vec<u8>, hound::Error> {
+    let spec = hound::WavSpec {
+        channels: decoded.channels as u16,
+        sample_rate: decoded.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut encoded = Cursor::new(vec![]);
+    let mut writer = hound::WavWriter::new(&mut encoded, spec)?;
+    for sample in &decoded.samples {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16)?;
+    }
+    writer.finalize()?;
+    Ok(encoded.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Half a second of silence, a second of a quiet tone and half a second of silence again
+    fn quiet_tone(sample_rate: u32) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut content = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut content, spec).unwrap();
+        let silence = sample_rate / 2;
+        for n in 0..silence * 4 {
+            let tone = n >= silence && n < silence * 3;
+            let t = n as f32 / sample_rate as f32;
+            let sample = if tone {
+                0.05 * (t * 440.0 * std::f32::consts::TAU).sin()
+            } else {
+                0.0
+            };
+            writer.write_sample((sample * 32767.0) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        content.into_inner()
+    }
+
+    /// Mono, 16 bits. hound only writes WAV
+    fn aiff(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+        let mut comm = vec![];
+        comm.extend_from_slice(&1u16.to_be_bytes());
+        comm.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+        comm.extend_from_slice(&16u16.to_be_bytes());
+        // The sample rate is an 80 bits extended float
+        let exponent = 63 - u64::from(sample_rate).leading_zeros();
+        comm.extend_from_slice(&(16383 + exponent as u16).to_be_bytes());
+        comm.extend_from_slice(&(u64::from(sample_rate) << (63 - exponent)).to_be_bytes());
+        let mut ssnd = vec![0; 8];
+        for sample in samples {
+            ssnd.extend_from_slice(&sample.to_be_bytes());
+        }
+
+        let mut content = b"AIFF".to_vec();
+        for (id, chunk) in [(b"COMM", comm), (b"SSND", ssnd)] {
+            content.extend_from_slice(id);
+            content.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            content.extend_from_slice(&chunk);
+        }
+        let mut form = b"FORM".to_vec();
+        form.extend_from_slice(&(content.len() as u32).to_be_bytes());
+        form.extend_from_slice(&content);
+        form
+    }
+
+    #[test]
+    fn trims_and_normalizes() {
+        let context = AssetContext::new("tone.wav", Some(0), Some("en"));
+        let processed = process(
+            quiet_tone(8000),
+            BinaryFormat::Wav,
+            &AudioOptions::default(),
+            &context,
+        )
+        .unwrap();
+        assert_eq!(processed.format, BinaryFormat::Wav);
+        // The tone starts, and ends, crossing zero: a sample or two quieter than the threshold
+        let properties = processed.properties;
+        assert!(
+            (990..=1000).contains(&properties.duration_ms),
+            "{properties:?}"
+        );
+        assert_eq!((properties.sample_rate, properties.channels), (8000, 1));
+
+        let samples: Vec<i16> = hound::WavReader::new(Cursor::new(processed.content))
+            .unwrap()
+            .into_samples()
+            .map(|s| s.unwrap())
+            .collect();
+        let rms = (samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64)
+            .sqrt()
+            / 32767.0;
+        // -20 dBFS is 0.1
+        assert!((rms - 0.1).abs() < 0.005, "{rms}");
+
+        let untouched = AudioOptions {
+            target_loudness_db: None,
+            trim_silence_db: None,
+            ..Default::default()
+        };
+        let processed = process(quiet_tone(8000), BinaryFormat::Wav, &untouched, &context).unwrap();
+        assert_eq!(processed.properties.duration_ms, 2000);
+
+        assert!(matches!(
+            process(
+                b"audio 02".to_vec(),
+                BinaryFormat::Ogg,
+                &untouched,
+                &context
+            ),
+            Err(FlipbookError::AudioProcessing { .. })
+        ));
+
+        // `channels` in the `fmt ` chunk
+        let mut silent = quiet_tone(8000);
+        silent[22..24].copy_from_slice(&0u16.to_le_bytes());
+        assert!(matches!(
+            process(silent.clone(), BinaryFormat::Wav, &untouched, &context),
+            Err(FlipbookError::AudioProcessing { .. })
+        ));
+        assert_eq!(duration_ms(silent, BinaryFormat::Wav), None);
+    }
+
+    #[test]
+    fn keeps_the_format_of_the_source() {
+        let context = AssetContext::new("tone.wav", Some(0), Some("en"));
+        let keep = AudioOptions {
+            encoding: None,
+            ..Default::default()
+        };
+        let processed = process(quiet_tone(8000), BinaryFormat::Wav, &keep, &context).unwrap();
+        assert_eq!(processed.format, BinaryFormat::Wav);
+        assert!(processed.properties.duration_ms < 2000);
+
+        // Anything else can be measured but not written again
+        let aiff = aiff(&[0, 1000, 0, -1000].repeat(2000), 8000);
+        let measure = AudioOptions {
+            target_loudness_db: None,
+            trim_silence_db: None,
+            encoding: None,
+        };
+        let processed = process(aiff.clone(), BinaryFormat::Aiff, &measure, &context).unwrap();
+        assert_eq!(processed.format, BinaryFormat::Aiff);
+        assert_eq!(processed.content, aiff);
+        assert_eq!(processed.properties.duration_ms, 1000);
+        assert!(matches!(
+            process(aiff, BinaryFormat::Aiff, &keep, &context),
+            Err(FlipbookError::AudioEncodingUnavailable {
+                format: BinaryFormat::Aiff,
+                ..
+            })
+        ));
+    }
+}
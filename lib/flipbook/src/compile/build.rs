@@ -1,108 +1,600 @@
-use anyhow::Result;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
 use base64::{engine::general_purpose, Engine};
 
-use crate::flipbook::package::{AudioDB, FilePositionInPackage, FlipbookPackage, TextDB};
+use crate::container;
+use crate::error::{AssetContext, FlipbookError, Result};
+use crate::flipbook::common::LanguageCode;
+use crate::flipbook::format::{BinaryFormat, MediaKind};
+use crate::flipbook::layout::check_block;
+use crate::flipbook::markup;
+use crate::flipbook::package::{
+    AudioDB, BlockInPackage, Digest, FilePositionInPackage, FlipbookPackage, PageInPackage,
+    StringID, TextDB, TimingDB, VariantName, VariantPackage, CURRENT_VERSION,
+};
+use crate::flipbook::source::{PageText, TextLocation};
+use crate::flipbook::timing;
+
+use super::common::{Arguments, Artifacts, Destination};
+use super::options::{ImageOptions, ImageVariant, MissingBackground};
+use super::report::CompileReport;
+use super::writer::PackageWriter;
+use super::{audio, images};
 
-use super::common::{Arguments, Artifacts};
+/// Packed instead of a missing background when `MissingBackground::Placeholder` is requested
+pub(super) const PLACEHOLDER_IMAGE: &[u8] = include_bytes!("../../assets/placeholder.jpg");
+const PLACEHOLDER_IMAGE_FORMAT: BinaryFormat = BinaryFormat::Jpg;
 
 pub fn build(args: &Arguments) -> Result<Artifacts> {
-    let miniature_data = std::fs::read(&args.source.miniature.path)?;
-    let miniature: String = general_purpose::STANDARD.encode(miniature_data);
+    check_blocks(args)?;
+    let texts = construct_texts(args)?;
+    let miniature = read_miniature(args)?;
+    let binary_package_url = binary_package_url(args)?;
+
+    // Assets go straight to disk, in the same order as always: backgrounds, audio and variants
+    let deduplicate = args.options.deduplicate_assets;
+    let mut writer = match args.destination {
+        Destination::Split { path_binary, .. } => PackageWriter::create(path_binary, deduplicate)?,
+        Destination::Container { path } => {
+            PackageWriter::new(BufWriter::new(container::create(path)?), path, deduplicate)
+        }
+    };
+
+    let backgrounds = construct_background_images(args, &mut writer)?;
 
-    let (images_in_pages, mut binary_package) = construct_background_images(args);
+    let audio_db = construct_audio_db(args, &mut writer)?;
 
-    let audio_db = construct_audio_db(args, &mut binary_package);
+    let (variants, variant_packages) = construct_variants(args, &mut writer)?;
+
+    let binary_summary = writer.finish()?;
+    tracing::debug!(
+        "Binary package `{}` written: {} bytes, {} saved reusing {} assets",
+        args.path_binary(),
+        binary_summary.length,
+        binary_summary.bytes_saved,
+        binary_summary.reused_assets
+    );
+    let report = CompileReport {
+        binary_package_length: binary_summary.length,
+        reused_assets: binary_summary.reused_assets,
+        bytes_saved: binary_summary.bytes_saved,
+        miniature_length: miniature.len() as u64,
+        ..Default::default()
+    };
+
+    let timings = construct_timing_db(args, &audio_db)?;
+
+    let assets = PackedAssets {
+        binary_package_digest: Some(binary_summary.digest),
+        backgrounds,
+        variants,
+        variant_packages,
+        audio: audio_db,
+    };
+    let metadata = construct_metadata(args, miniature, binary_package_url, assets, texts, timings);
 
-    let page_texts = construct_text_db(args);
+    let cr = Artifacts { metadata, report };
+    Ok(cr)
+}
+
+/// Builds the metadata again keeping the assets already in the binary package, as described by
+///   `previous`. Only valid when backgrounds and audio are the same files they were, and the
+///   variants are the same too
+pub fn rebuild_metadata(
+    args: &Arguments,
+    previous: &FlipbookPackage,
+    report: CompileReport,
+) -> Result<Artifacts> {
+    check_blocks(args)?;
+    let texts = construct_texts(args)?;
+    let assets = PackedAssets {
+        binary_package_digest: previous.binary_package_digest.clone(),
+        backgrounds: previous.pages.iter().map(|p| p.image.clone()).collect(),
+        variants: previous.pages.iter().map(|p| p.variants.clone()).collect(),
+        variant_packages: previous.variant_packages.clone(),
+        audio: previous.audio.clone(),
+    };
+    let miniature = read_miniature(args)?;
+    let report = CompileReport {
+        miniature_length: miniature.len() as u64,
+        ..report
+    };
+    let timings = construct_timing_db(args, &assets.audio)?;
+    let metadata = construct_metadata(
+        args,
+        miniature,
+        binary_package_url(args)?,
+        assets,
+        texts,
+        timings,
+    );
+    Ok(Artifacts { metadata, report })
+}
+
+fn binary_package_url(args: &Arguments) -> Result<String> {
+    args.binary_package_url()
+        .ok_or_else(|| FlipbookError::InvalidOutputPath {
+            path: args.path_binary().to_string(),
+        })
+}
+
+/// The miniature as it's embedded in the metadata, see `MiniatureOptions`
+fn read_miniature(args: &Arguments) -> Result<Vec<u8>> {
+    let path = args
+        .source
+        .miniature_source()
+        .ok_or(FlipbookError::NoMiniature)?;
+    // Made from the first page when the source has no miniature
+    let page = args.source.miniature.is_none().then_some(0);
+    let context = AssetContext::new(&path, page, None);
+    let content =
+        std::fs::read(&path).map_err(|e| FlipbookError::from_asset_read(context.clone(), e))?;
+    images::miniature(&content, &args.options.miniature, &context)
+}
+
+/// Everything but the binary package: texts, timings, pages and the embedded miniature
+fn construct_metadata(
+    args: &Arguments,
+    miniature: Vec<u8>,
+    binary_package_url: String,
+    assets: PackedAssets,
+    texts: Texts,
+    timings: TimingDB,
+) -> FlipbookPackage {
+    let pages = construct_pages(args, assets.backgrounds, assets.variants);
 
     let title_sid = format!("TITLE_{}", args.source.default_language);
     let summary_sid = format!("SUMMARY_{}", args.source.default_language);
 
-    let metadata = FlipbookPackage {
-        version: args.source.version.clone(),
+    FlipbookPackage {
+        version: CURRENT_VERSION,
         languages: args.source.languages.clone(),
         default_language: args.source.default_language.clone(),
-        binary_package_url: args.binary_file_path().unwrap(),
-        texts: page_texts,
-        audio: audio_db,
+        fallbacks: args.source.fallbacks.clone(),
+        binary_package_url,
+        binary_package_digest: assets.binary_package_digest,
+        texts: texts.plain,
+        markup: texts.markup,
+        audio: assets.audio,
+        timings,
         title: title_sid,
         summary: summary_sid,
-        miniature,
-        images_in_pages,
-    };
+        miniature: general_purpose::STANDARD.encode(miniature),
+        pages,
+        variant_packages: assets.variant_packages,
+    }
+}
 
-    let cr = Artifacts {
-        metadata,
-        binary_package,
+/// The content of an asset decides its format, the extension is only trusted for content that
+///   can't be recognized. With `CompileOptions::strict_formats` both have to agree
+fn asset_format(args: &Arguments, context: &AssetContext, kind: MediaKind) -> Result<BinaryFormat> {
+    let strict = args.options.strict_formats;
+    let claimed = BinaryFormat::from_path(&context.path);
+    let sniffed = BinaryFormat::sniff_file(&context.path)
+        .map_err(|e| FlipbookError::from_asset_read(context.clone(), e))?;
+
+    let format = match (sniffed, claimed) {
+        (Some(sniffed), claimed) if claimed != Some(sniffed) => {
+            if strict {
+                return Err(FlipbookError::FormatMismatch {
+                    context: context.clone(),
+                    content: sniffed,
+                });
+            }
+            tracing::warn!("Content is {} despite its extension: {}", sniffed, context);
+            sniffed
+        }
+        (Some(sniffed), _) => sniffed,
+        (None, Some(claimed)) if !strict => {
+            tracing::warn!(
+                "Content not recognized, trusting the extension: {}",
+                context
+            );
+            claimed
+        }
+        (None, _) => {
+            return Err(FlipbookError::UnsupportedFormat {
+                context: context.clone(),
+            })
+        }
     };
-    Ok(cr)
-}
 
-fn file_extension(path: &str) -> String {
-    std::path::Path::new(path)
-        .extension()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string()
+    if format.kind() != kind {
+        return Err(FlipbookError::UnsupportedFormat {
+            context: context.clone(),
+        });
+    }
+    Ok(format)
 }
 
-fn append_file_to_binary_package(
-    binary_package: &mut Vec<u8>,
-    path: &str,
-) -> FilePositionInPackage {
-    let mut file_content = std::fs::read(&path).unwrap();
-
-    let fip = FilePositionInPackage {
-        format: file_extension(&path),
-        start: binary_package.len() as u64,
-        length: file_content.len() as u64,
-    };
+/// Text and audio of a page share the same StringID: PAGE_<page no.>_<lang>, and
+///   PAGE_<page no.>_BLOCK_<block no.>_<lang> for the blocks of the page
+pub(super) fn page_string_id(location: TextLocation, language: &str) -> StringID {
+    match location.block {
+        None => format!("PAGE_{}_{language}", location.page),
+        Some(block) => format!("PAGE_{}_BLOCK_{block}_{language}", location.page),
+    }
+}
 
-    binary_package.append(&mut file_content);
-    fip
+/// Every block has to fit in its page, see `layout::check_block`
+fn check_blocks(args: &Arguments) -> Result<()> {
+    for (page, source_page) in args.source.pages.iter().enumerate() {
+        for (block, text_block) in source_page.blocks.iter().enumerate() {
+            if let Some(reason) = check_block(&text_block.rect, text_block.font_size) {
+                return Err(FlipbookError::InvalidTextBlock {
+                    page,
+                    block,
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
 }
 
-fn construct_audio_db(args: &Arguments, binary_package: &mut Vec<u8>) -> AudioDB {
+fn construct_audio_db<W: Write>(
+    args: &Arguments,
+    writer: &mut PackageWriter<W>,
+) -> Result<AudioDB> {
     let mut audio_db = AudioDB::default();
     let audios = args.source.pages_audios();
     for a in audios {
-        let audio_id = format!("PAGE_{}_{}", a.0.to_string(), a.1);
-        let fip = append_file_to_binary_package(binary_package, &a.2);
+        let audio_id = page_string_id(a.0, &a.1);
+        let context = AssetContext::new(&a.2, Some(a.0.page), Some(&a.1));
+        let format = asset_format(args, &context, MediaKind::Audio)?;
+        let fip = match &args.options.audio {
+            Some(options) => {
+                let content = std::fs::read(&context.path)
+                    .map_err(|e| FlipbookError::from_asset_read(context.clone(), e))?;
+                let processed = audio::process(content, format, options, &context)?;
+                let mut fip = writer.append_bytes(processed.format, &processed.content)?;
+                fip.audio_properties = Some(processed.properties);
+                fip
+            }
+            None => writer.append_file(format, context)?,
+        };
         audio_db.insert(audio_id, fip);
     }
-    audio_db
+    Ok(audio_db)
+}
+
+/// Reads the timing tracks of every page and checks they fit in the audio they go with, as packed
+///   in `audio_db`. A track without audio is kept, the client may still use it for pacing
+fn construct_timing_db(args: &Arguments, audio_db: &AudioDB) -> Result<TimingDB> {
+    let mut timing_db = TimingDB::default();
+    for (location, lang, file) in args.source.pages_timings() {
+        let context = AssetContext::new(&file.path, Some(location.page), Some(&lang));
+        let track = timing::load(&file, &context)?;
+        let string_id = page_string_id(location, &lang);
+        match audio_db.get(&string_id) {
+            Some(fip) => {
+                if let Some(duration_ms) = audio_duration_ms(args, location, &lang, fip) {
+                    timing::check_duration(&track, duration_ms, &context)?;
+                } else {
+                    tracing::warn!("Can't tell how long the audio is, not checking {}", context);
+                }
+            }
+            None => tracing::warn!("Timing track without audio: {}", context),
+        }
+        timing_db.entry(string_id).or_default().push(track);
+    }
+    Ok(timing_db)
+}
+
+/// From the package when the audio stage measured it, from the source audio otherwise
+fn audio_duration_ms(
+    args: &Arguments,
+    location: TextLocation,
+    language: &str,
+    fip: &FilePositionInPackage,
+) -> Option<u64> {
+    if let Some(properties) = &fip.audio_properties {
+        return Some(properties.duration_ms);
+    }
+    let path = args
+        .source
+        .pages_audios()
+        .into_iter()
+        .find(|(l, lang, _)| *l == location && lang == language)?
+        .2;
+    audio::duration_ms(std::fs::read(path).ok()?, fip.format)
+}
+
+/// One entry per page, `None` for the pages left without a background
+type Backgrounds = Vec<Option<FilePositionInPackage>>;
+
+/// One entry per page, with the background in every variant of `CompileOptions::image_variants`
+type PageVariants = Vec<BTreeMap<VariantName, FilePositionInPackage>>;
+
+/// Where the assets ended up, what the metadata describes
+struct PackedAssets {
+    binary_package_digest: Option<Digest>,
+    backgrounds: Backgrounds,
+    variants: PageVariants,
+    variant_packages: BTreeMap<VariantName, VariantPackage>,
+    audio: AudioDB,
 }
 
-fn construct_background_images(args: &Arguments) -> (Vec<FilePositionInPackage>, Vec<u8>) {
+fn construct_background_images<W: Write>(
+    args: &Arguments,
+    writer: &mut PackageWriter<W>,
+) -> Result<Backgrounds> {
     let mut fpip = vec![];
-    let mut binary_package = vec![];
+    // The placeholder is stored once and shared by every page that needs it
+    let mut placeholder: Option<FilePositionInPackage> = None;
+
+    for (pos, p) in args.source.pages.iter().enumerate() {
+        let path = args.source.resolve(&p.background.path);
+        let context = AssetContext::new(&path, Some(pos), None);
 
-    for p in &args.source.pages {
-        let image_path = std::path::Path::new(&p.background.path);
-        if !image_path.exists() {
+        if Path::new(&path).exists() {
+            fpip.push(Some(append_background(args, writer, context)?));
             continue;
         }
 
-        let fip = append_file_to_binary_package(&mut binary_package, &p.background.path);
+        match args.options.missing_background {
+            MissingBackground::Fail => return Err(FlipbookError::AssetMissing { context }),
+            MissingBackground::Placeholder => {
+                tracing::warn!("Background missing, using a placeholder: {}", context);
+                if placeholder.is_none() {
+                    placeholder = Some(match &args.options.images {
+                        Some(options) => {
+                            append_processed(writer, PLACEHOLDER_IMAGE, options, &context)?
+                        }
+                        None => writer.append_bytes(PLACEHOLDER_IMAGE_FORMAT, PLACEHOLDER_IMAGE)?,
+                    });
+                }
+                fpip.push(placeholder.clone());
+            }
+            MissingBackground::LeaveEmpty => {
+                tracing::warn!("Background missing, leaving the page empty: {}", context);
+                fpip.push(None);
+            }
+        }
+    }
 
-        fpip.push(fip);
+    Ok(fpip)
+}
+
+/// Packs a background, through the image stage when `CompileOptions::images` asks for it
+fn append_background<W: Write>(
+    args: &Arguments,
+    writer: &mut PackageWriter<W>,
+    context: AssetContext,
+) -> Result<FilePositionInPackage> {
+    let format = asset_format(args, &context, MediaKind::Image)?;
+    let Some(options) = &args.options.images else {
+        return writer.append_file(format, context);
+    };
+    let content = std::fs::read(&context.path)
+        .map_err(|e| FlipbookError::from_asset_read(context.clone(), e))?;
+    append_processed(writer, &content, options, &context)
+}
+
+fn append_processed<W: Write>(
+    writer: &mut PackageWriter<W>,
+    content: &[u8],
+    options: &ImageOptions,
+    context: &AssetContext,
+) -> Result<FilePositionInPackage> {
+    let processed = images::process(content, options, context)?;
+    // Set even when the writer reuses a stored copy, same content means same dimensions
+    let mut fip = writer.append_bytes(processed.format, &processed.content)?;
+    fip.dimensions = Some(processed.dimensions);
+    Ok(fip)
+}
+
+/// Variants go, one after the other, into the main binary package or into packages of their own
+fn construct_variants<W: Write>(
+    args: &Arguments,
+    writer: &mut PackageWriter<W>,
+) -> Result<(PageVariants, BTreeMap<VariantName, VariantPackage>)> {
+    let mut variants = vec![BTreeMap::default(); args.source.pages.len()];
+    let mut packages = BTreeMap::default();
+
+    for (pos, variant) in args.options.image_variants.iter().enumerate() {
+        check_variant(variant, &args.options.image_variants[..pos])?;
+        let fips = match args.path_variant_binary(&variant.name) {
+            Some(path) => {
+                let path = path.to_string_lossy();
+                let mut variant_writer =
+                    PackageWriter::create(&path, args.options.deduplicate_assets)?;
+                let fips = append_variant(args, &mut variant_writer, variant)?;
+                let summary = variant_writer.finish()?;
+                let url = Arguments::file_name(Path::new(path.as_ref())).ok_or_else(|| {
+                    FlipbookError::InvalidOutputPath {
+                        path: path.to_string(),
+                    }
+                })?;
+                let package = VariantPackage {
+                    url,
+                    digest: Some(summary.digest),
+                };
+                packages.insert(variant.name.clone(), package);
+                fips
+            }
+            None => append_variant(args, writer, variant)?,
+        };
+        for (page_variants, fip) in variants.iter_mut().zip(fips) {
+            if let Some(fip) = fip {
+                page_variants.insert(variant.name.clone(), fip);
+            }
+        }
     }
 
-    (fpip, binary_package)
+    Ok((variants, packages))
+}
+
+/// The name of a variant ends up in a file name, it has to be a simple one
+fn check_variant(variant: &ImageVariant, previous: &[ImageVariant]) -> Result<()> {
+    let invalid = |reason: &str| FlipbookError::InvalidImageVariant {
+        name: variant.name.clone(),
+        reason: reason.to_string(),
+    };
+    let simple = variant
+        .name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if variant.name.is_empty() || !simple {
+        return Err(invalid("only letters, digits, `-` and `_` are allowed"));
+    }
+    if previous.iter().any(|v| v.name == variant.name) {
+        return Err(invalid("there's another variant with that name"));
+    }
+    Ok(())
+}
+
+/// The background of every page in `variant`. Pages without one get the placeholder in the
+///   variant too, if that's what they got as their image
+fn append_variant<W: Write>(
+    args: &Arguments,
+    writer: &mut PackageWriter<W>,
+    variant: &ImageVariant,
+) -> Result<Vec<Option<FilePositionInPackage>>> {
+    let mut fips = vec![];
+    for (pos, p) in args.source.pages.iter().enumerate() {
+        let path = args.source.resolve(&p.background.path);
+        let context = AssetContext::new(&path, Some(pos), None);
+        let content = if Path::new(&path).exists() {
+            let content = std::fs::read(&path)
+                .map_err(|e| FlipbookError::from_asset_read(context.clone(), e))?;
+            Cow::Owned(content)
+        } else if args.options.missing_background == MissingBackground::Placeholder {
+            Cow::Borrowed(PLACEHOLDER_IMAGE)
+        } else {
+            fips.push(None);
+            continue;
+        };
+        fips.push(Some(append_processed(
+            writer,
+            &content,
+            &variant.image,
+            &context,
+        )?));
+    }
+    Ok(fips)
+}
+
+/// Links every page to its image, variants, texts, audios and blocks
+fn construct_pages(
+    args: &Arguments,
+    backgrounds: Backgrounds,
+    variants: PageVariants,
+) -> Vec<PageInPackage> {
+    args.source
+        .pages
+        .iter()
+        .zip(backgrounds.into_iter().zip(variants))
+        .enumerate()
+        .map(|(pos, (source_page, (image, variants)))| {
+            let mut page = PageInPackage {
+                image,
+                variants,
+                ..Default::default()
+            };
+            if let Some(text) = &source_page.text {
+                let location = TextLocation {
+                    page: pos,
+                    block: None,
+                };
+                (page.texts, page.audio) = string_ids(args, location, text);
+            }
+            for (block, text_block) in source_page.blocks.iter().enumerate() {
+                let location = TextLocation {
+                    page: pos,
+                    block: Some(block),
+                };
+                let (texts, audio) = string_ids(args, location, &text_block.text);
+                page.blocks.push(BlockInPackage {
+                    rect: text_block.rect,
+                    align: text_block.align,
+                    font_size: text_block.font_size,
+                    texts,
+                    audio,
+                });
+            }
+            page
+        })
+        .collect()
+}
+
+/// Language -> StringID
+type StringIDs = BTreeMap<LanguageCode, StringID>;
+
+/// The StringIDs of the texts, and of the audios, in `text`. A language of the book without a
+///   translation points at the one of its fallback, see `FlipbookSource::text_for`. The audio only
+///   comes along with the text: a translation is never read in another language
+fn string_ids(args: &Arguments, location: TextLocation, text: &PageText) -> (StringIDs, StringIDs) {
+    let mut texts: StringIDs = text
+        .texts()
+        .into_iter()
+        .map(|(lang, _)| {
+            let sid = page_string_id(location, &lang);
+            (lang, sid)
+        })
+        .collect();
+    let mut audio: StringIDs = text
+        .audios()
+        .into_iter()
+        .map(|(lang, _)| {
+            let sid = page_string_id(location, &lang);
+            (lang, sid)
+        })
+        .collect();
+    for lang in &args.source.languages {
+        if texts.contains_key(lang) {
+            continue;
+        }
+        let Some((from, asset)) = args.source.text_for(text, lang) else {
+            continue;
+        };
+        let sid = page_string_id(location, from);
+        if asset.audio.is_some() {
+            audio.insert(lang.clone(), sid.clone());
+        }
+        texts.insert(lang.clone(), sid);
+    }
+    (texts, audio)
+}
+
+/// Every text of the book, split in the plain text and the markup, see `markup::compile`
+#[derive(Default)]
+struct Texts {
+    plain: TextDB,
+    markup: TextDB,
+}
+
+fn construct_texts(args: &Arguments) -> Result<Texts> {
+    let mut texts = Texts::default();
+    for (sid, text) in construct_text_db(args) {
+        let (plain, markup) =
+            markup::compile(&text).map_err(|source| FlipbookError::InvalidMarkup {
+                string_id: sid.clone(),
+                source,
+            })?;
+        if let Some(markup) = markup {
+            texts.markup.insert(sid.clone(), markup);
+        }
+        texts.plain.insert(sid, plain);
+    }
+    Ok(texts)
 }
 
 /// Traverses the source structure gathering all the texts present, using for stringIDs
 /// TITLE_<lang>
 /// SUMMARY_<lang>
 /// PAGE_<page no.>_<lang>  
+/// PAGE_<page no.>_BLOCK_<block no.>_<lang>
 fn construct_text_db(args: &Arguments) -> TextDB {
     let mut tdb = TextDB::default();
 
     let pages_texts = args.source.pages_text();
 
     for pt in &pages_texts {
-        let _ = tdb.insert(format!("PAGE_{}_{}", pt.0.to_string(), pt.1), pt.2.clone());
+        let _ = tdb.insert(page_string_id(pt.0, &pt.1), pt.2.clone());
     }
 
     for title_texts in &args.source.title.texts() {
@@ -1,24 +1,69 @@
+use std::path::{Path, PathBuf};
+
 use crate::flipbook::package::FlipbookPackage;
 use crate::flipbook::source::FlipbookSource;
-/// Objects generated through a compilation of the sources (build module and associates)
+
+use super::options::CompileOptions;
+use super::report::CompileReport;
+
+/// Objects generated through a compilation of the sources (build module and associates). The
+///   binary package isn't here: it's streamed to `Arguments::path_binary` while building
 pub struct Artifacts {
     pub metadata: FlipbookPackage,
-    pub binary_package: Vec<u8>,
+    pub report: CompileReport,
 }
+/// Where the compiled flipbook goes
+pub enum Destination<'a> {
+    /// Metadata and binary package as 2 files
+    Split {
+        path_metadata: &'a str,
+        path_binary: &'a str,
+    },
+    /// A single file, see `crate::container`
+    Container { path: &'a str },
+}
+
 /// How the process was invoked
 pub struct Arguments<'a> {
     pub source: &'a FlipbookSource,
-    pub path_metadata: &'a str,
-    pub path_binary: &'a str,
+    pub destination: Destination<'a>,
+    pub options: &'a CompileOptions,
 }
 
 impl<'a> Arguments<'a> {
-    fn file_name(path: &'a str) -> Option<String> {
-        let as_path = std::path::Path::new(path);
-        Some(as_path.file_name()?.to_str()?.to_string())
+    pub fn file_name(path: &Path) -> Option<String> {
+        Some(path.file_name()?.to_str()?.to_string())
+    }
+
+    /// Where the assets are written
+    pub fn path_binary(&self) -> &'a str {
+        match self.destination {
+            Destination::Split { path_binary, .. } => path_binary,
+            Destination::Container { path } => path,
+        }
+    }
+
+    /// Binary package of a variant with one of its own, next to the main one: `book.bin` ->
+    ///   `book.<variant>.bin`. `None` when the variant goes into the main binary package
+    pub fn path_variant_binary(&self, variant: &str) -> Option<PathBuf> {
+        match self.destination {
+            Destination::Split { path_binary, .. } if self.options.variant_packages => {
+                let path = Path::new(path_binary);
+                Some(match path.extension().and_then(|e| e.to_str()) {
+                    Some(extension) => path.with_extension(format!("{variant}.{extension}")),
+                    None => path.with_extension(variant),
+                })
+            }
+            _ => None,
+        }
     }
 
-    pub fn binary_file_path(&self) -> Option<String> {
-        Arguments::file_name(self.path_binary)
+    /// How the metadata points at the binary package: its file name, expected next to the
+    ///   metadata. A container carries its own assets so it's left empty
+    pub fn binary_package_url(&self) -> Option<String> {
+        match self.destination {
+            Destination::Split { path_binary, .. } => Arguments::file_name(Path::new(path_binary)),
+            Destination::Container { .. } => Some(String::new()),
+        }
     }
 }
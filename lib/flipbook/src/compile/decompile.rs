@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::{FlipbookError, Result};
+use crate::flipbook::common::{FilePath, LanguageCode};
+use crate::flipbook::source::{
+    Asset, Audio, FlipbookSource, Image, PageText, SourcePage, TextBlock, TextLocation,
+    SOURCE_VERSION,
+};
+use crate::flipbook::timing::{TimingFile, TimingFormat};
+use crate::reader::{LoadedAsset, PackageReader};
+
+/// Name of the source flipbook written by `decompile` inside its output directory
+pub const DECOMPILED_SOURCE_NAME: &str = "source.json";
+
+/// The metadata doesn't record the format of the miniature, JPEG is what the authors use
+const MINIATURE_FORMAT: &str = "jpg";
+
+/// What a string ID of the text or audio databases points at
+enum Entry {
+    Title(LanguageCode),
+    Summary(LanguageCode),
+    Page(TextLocation, LanguageCode),
+}
+
+/// Reverses the string IDs generated by `build`: TITLE_<lang>, SUMMARY_<lang>,
+///   PAGE_<page no.>_<lang> and PAGE_<page no.>_BLOCK_<block no.>_<lang>
+fn parse_string_id(sid: &str) -> Option<Entry> {
+    if let Some(lang) = sid.strip_prefix("TITLE_") {
+        return Some(Entry::Title(lang.to_string()));
+    }
+    if let Some(lang) = sid.strip_prefix("SUMMARY_") {
+        return Some(Entry::Summary(lang.to_string()));
+    }
+    let (page, rest) = sid.strip_prefix("PAGE_")?.split_once('_')?;
+    let page = page.parse().ok()?;
+    let (block, lang) = match rest.strip_prefix("BLOCK_") {
+        Some(rest) => {
+            let (block, lang) = rest.split_once('_')?;
+            (Some(block.parse().ok()?), lang)
+        }
+        None => (None, rest),
+    };
+    Some(Entry::Page(TextLocation { page, block }, lang.to_string()))
+}
+
+/// Writes `content` under `output_dir`, returning the path to reference it with: relative to the
+///   source, written in the same directory
+fn write_asset(output_dir: &Path, name: &str, content: &[u8]) -> Result<FilePath> {
+    let path = output_dir.join(name);
+    std::fs::write(&path, content).map_err(|source| FlipbookError::OutputWrite {
+        path: path.to_string_lossy().to_string(),
+        source,
+    })?;
+    Ok(name.to_string())
+}
+
+/// Name the assets of a text start with: `<page>_<lang>` or `<page>_b<block>_<lang>`
+fn text_stem(location: TextLocation, language: &str) -> String {
+    match location.block {
+        Some(block) => format!("{:02}_b{block}_{language}", location.page),
+        None => format!("{:02}_{language}", location.page),
+    }
+}
+
+fn image_name(page: usize, image: &LoadedAsset) -> String {
+    format!("{page:02}.{}", image.format)
+}
+
+fn entry<'a>(text: &'a mut PageText, language: &str) -> &'a mut Asset {
+    text.0.entry(language.to_string()).or_default()
+}
+
+/// Recreates a source flipbook out of a compiled one: every asset is extracted into
+///   `output_dir` and the source, pointing at them, written as `DECOMPILED_SOURCE_NAME` next to
+///   them. Compiling the result yields an equivalent book
+/// Pages compiled without a background point at a file that doesn't exist, so they're left empty
+///   again when compiling with `MissingBackground::LeaveEmpty`
+pub fn decompile(reader: &mut PackageReader, output_dir: &str) -> Result<FlipbookSource> {
+    let output_error = |source| FlipbookError::OutputWrite {
+        path: output_dir.to_string(),
+        source,
+    };
+    let output = Path::new(output_dir);
+    std::fs::create_dir_all(output).map_err(output_error)?;
+
+    let miniature = write_asset(
+        output,
+        &format!("miniature.{MINIATURE_FORMAT}"),
+        &reader.miniature()?,
+    )?;
+
+    let mut title = PageText::default();
+    let mut summary = PageText::default();
+    let mut page_texts: HashMap<TextLocation, PageText> = HashMap::default();
+    for (sid, text) in &reader.package().texts {
+        // The markup is what the author wrote, the plain text is made from it
+        let text = reader.package().markup.get(sid).unwrap_or(text);
+        match parse_string_id(sid) {
+            Some(Entry::Title(lang)) => entry(&mut title, &lang).text = text.clone(),
+            Some(Entry::Summary(lang)) => entry(&mut summary, &lang).text = text.clone(),
+            Some(Entry::Page(location, lang)) => {
+                entry(page_texts.entry(location).or_default(), &lang).text = text.clone();
+            }
+            None => tracing::warn!("Unknown string ID `{}`, ignored", sid),
+        }
+    }
+
+    let audio: Vec<(TextLocation, LanguageCode)> = reader
+        .package()
+        .audio
+        .keys()
+        .filter_map(|sid| match parse_string_id(sid) {
+            Some(Entry::Page(location, lang)) => Some((location, lang)),
+            _ => {
+                tracing::warn!("Audio under unknown string ID `{}`, ignored", sid);
+                None
+            }
+        })
+        .collect();
+    for (location, lang) in audio {
+        let asset = match location.block {
+            Some(block) => reader.block_audio(location.page, block, &lang)?,
+            None => reader.audio(location.page, &lang)?,
+        };
+        let Some(asset) = asset else {
+            tracing::warn!("Audio of {:?} `{}` not linked to its page", location, lang);
+            continue;
+        };
+        let name = format!("{}.{}", text_stem(location, &lang), asset.format);
+        let path = write_asset(output, &name, &asset.content)?;
+        entry(page_texts.entry(location).or_default(), &lang).audio = Some(Audio { path });
+    }
+
+    // Timing tracks come back as WebVTT, whatever they were written in
+    for (sid, tracks) in &reader.package().timings {
+        let Some(Entry::Page(location, lang)) = parse_string_id(sid) else {
+            tracing::warn!("Timing tracks under unknown string ID `{}`, ignored", sid);
+            continue;
+        };
+        let mut timings = vec![];
+        for (pos, track) in tracks.iter().enumerate() {
+            let stem = text_stem(location, &lang);
+            let name = match pos {
+                0 => format!("{stem}.{}.vtt", track.kind.as_str()),
+                _ => format!("{stem}.{}.{pos}.vtt", track.kind.as_str()),
+            };
+            let path = write_asset(output, &name, track.to_webvtt().as_bytes())?;
+            timings.push(TimingFile {
+                path,
+                kind: track.kind,
+                format: Some(TimingFormat::WebVtt),
+            });
+        }
+        entry(page_texts.entry(location).or_default(), &lang).timings = timings;
+    }
+
+    let mut pages = vec![];
+    for page in 0..reader.page_count() {
+        let path = match reader.image(page)? {
+            Some(image) => write_asset(output, &image_name(page, &image), &image.content)?,
+            None => {
+                tracing::warn!("Page {} has no background", page);
+                format!("{page:02}.jpg")
+            }
+        };
+        let blocks = reader
+            .page(page)
+            .map(|p| p.blocks.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .map(|(block, b)| TextBlock {
+                rect: b.rect,
+                align: b.align,
+                font_size: b.font_size,
+                text: page_texts
+                    .remove(&TextLocation {
+                        page,
+                        block: Some(block),
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect();
+        pages.push(SourcePage {
+            background: Image { path },
+            text: page_texts.remove(&TextLocation { page, block: None }),
+            blocks,
+        });
+    }
+
+    let source = FlipbookSource {
+        version: SOURCE_VERSION,
+        languages: reader.languages().to_vec(),
+        default_language: reader.default_language().to_string(),
+        fallbacks: reader.package().fallbacks.clone(),
+        title,
+        summary,
+        miniature: Some(Image { path: miniature }),
+        pages,
+        asset_root: Some(std::path::absolute(output).map_err(output_error)?),
+    };
+
+    let path_source = output.join(DECOMPILED_SOURCE_NAME);
+    let source_error = |source| FlipbookError::OutputWrite {
+        path: path_source.to_string_lossy().to_string(),
+        source,
+    };
+    let f_source = std::fs::File::create(&path_source).map_err(source_error)?;
+    serde_json::to_writer_pretty(f_source, &source).map_err(|source| {
+        FlipbookError::MetadataSerialization {
+            path: path_source.to_string_lossy().to_string(),
+            source,
+        }
+    })?;
+
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    use crate::test_utils::{
+        compile_source, path_binary, sample_options, sample_source, scratch_dir,
+    };
+
+    #[test]
+    fn decompile_round_trip() {
+        let dir = scratch_dir("decompile");
+        let mut source = sample_source(&dir);
+        // Only brackets, no style: the plain text alone would read `[b]` as a tag
+        let text = source.pages[1].text.as_mut().unwrap();
+        text.0.get_mut("en").unwrap().text = "see [[b] and [note]".to_string();
+        let mut reader = compile_source(&dir, &source, &sample_options()).unwrap();
+        assert_eq!(reader.text(1, "en").unwrap(), "see [b] and [note]");
+        let path_decompiled = dir.join("decompiled");
+        let source = decompile(&mut reader, path_decompiled.to_str().unwrap()).unwrap();
+        assert_eq!(source.pages.len(), 3);
+        assert_eq!(source.version, SOURCE_VERSION);
+
+        let path_source = path_decompiled.join(DECOMPILED_SOURCE_NAME);
+        let source = FlipbookSource::from_file(path_source.to_str().unwrap()).unwrap();
+        let path_recompiled = dir.join("recompiled");
+        std::fs::create_dir_all(&path_recompiled).unwrap();
+        let recompiled = compile_source(&path_recompiled, &source, &sample_options()).unwrap();
+
+        // The miniature is encoded again on every compilation, only its pixels survive
+        let dimensions = |reader: &PackageReader| {
+            image::load_from_memory(&reader.miniature().unwrap())
+                .unwrap()
+                .dimensions()
+        };
+        assert_eq!(dimensions(&recompiled), dimensions(&reader));
+
+        let (original, recompiled) = (reader.package(), recompiled.package());
+        assert_eq!(recompiled.texts, original.texts);
+        assert!(recompiled.pages[1].image.is_none());
+        assert_eq!(
+            std::fs::read(path_binary(&path_recompiled)).unwrap(),
+            std::fs::read(path_binary(&dir)).unwrap()
+        );
+    }
+}
@@ -0,0 +1,336 @@
+// The image stage of the compilation. Backgrounds come from illustrators in whatever size and
+//   format they had at hand, here they're decoded and brought to the resolution the client is
+//   designed for, so every book looks the same on the tablet.
+// Encoding again from the pixels drops EXIF and the rest of metadata in the original file.
+// The miniature goes through here too, on its way into the metadata.
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+
+use crate::error::{AssetContext, FlipbookError, Result};
+use crate::flipbook::format::BinaryFormat;
+use crate::flipbook::package::Dimensions;
+
+use super::options::{ImageEncoding, ImageOptions, MiniatureOptions, ResizeMode};
+
+const RESIZE_FILTER: FilterType = FilterType::Lanczos3;
+
+/// An image ready to be packed
+pub struct ProcessedImage {
+    pub format: BinaryFormat,
+    pub content: Vec<u8>,
+    pub dimensions: Dimensions,
+}
+
+/// Decodes `content`, whatever its format, and encodes it again as `options` says
+pub fn process(
+    content: &[u8],
+    options: &ImageOptions,
+    context: &AssetContext,
+) -> Result<ProcessedImage> {
+    let resized = resize(&decode(content, context)?, options);
+    let (format, content) = encode(&resized, options.encoding, context)?;
+    Ok(ProcessedImage {
+        format,
+        content,
+        dimensions: Dimensions {
+            width: resized.width(),
+            height: resized.height(),
+        },
+    })
+}
+
+/// Downscales `content` to fit in `options`, as a JPEG. It's encoded again even when it already
+///   fits, the miniature is public and mustn't carry the EXIF of the original
+pub fn miniature(
+    content: &[u8],
+    options: &MiniatureOptions,
+    context: &AssetContext,
+) -> Result<Vec<u8>> {
+    let decoded = decode(content, context)?;
+    let (max_width, max_height) = (options.max_width.max(1), options.max_height.max(1));
+    let fits = decoded.width() <= max_width && decoded.height() <= max_height;
+    let scaled = if fits {
+        decoded
+    } else {
+        decoded.resize(max_width, max_height, RESIZE_FILTER)
+    };
+    let encoding = ImageEncoding::Jpg {
+        quality: options.quality,
+    };
+    Ok(encode(&scaled, encoding, context)?.1)
+}
+
+fn processing_error(context: &AssetContext, source: image::ImageError) -> FlipbookError {
+    FlipbookError::ImageProcessing {
+        context: context.clone(),
+        source: Box::new(source),
+    }
+}
+
+fn decode(content: &[u8], context: &AssetContext) -> Result<DynamicImage> {
+    image::load_from_memory(content).map_err(|e| processing_error(context, e))
+}
+
+/// Encoding from the pixels drops whatever metadata the original had
+fn encode(
+    image: &DynamicImage,
+    encoding: ImageEncoding,
+    context: &AssetContext,
+) -> Result<(BinaryFormat, Vec<u8>)> {
+    let mut encoded = Cursor::new(vec![]);
+    let format = match encoding {
+        ImageEncoding::Jpg { quality } => {
+            JpegEncoder::new_with_quality(&mut encoded, quality.clamp(1, 100))
+                .encode_image(&image.to_rgb8())
+                .map_err(|e| processing_error(context, e))?;
+            BinaryFormat::Jpg
+        }
+        ImageEncoding::Png => {
+            image
+                .write_to(&mut encoded, ImageOutputFormat::Png)
+                .map_err(|e| processing_error(context, e))?;
+            BinaryFormat::Png
+        }
+    };
+    Ok((format, encoded.into_inner()))
+}
+
+fn resize(image: &DynamicImage, options: &ImageOptions) -> DynamicImage {
+    let (width, height) = (options.width.max(1), options.height.max(1));
+    match options.resize {
+        ResizeMode::Fit => image.resize(width, height, RESIZE_FILTER),
+        ResizeMode::Fill => image.resize_to_fill(width, height, RESIZE_FILTER),
+        ResizeMode::Letterbox => {
+            let fitted = image.resize(width, height, RESIZE_FILTER).to_rgb8();
+            let mut canvas = RgbImage::from_pixel(width, height, Rgb(options.letterbox_color));
+            let x = (width - fitted.width()) / 2;
+            let y = (height - fitted.height()) / 2;
+            image::imageops::overlay(&mut canvas, &fitted, x.into(), y.into());
+            DynamicImage::ImageRgb8(canvas)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resizes_as_requested() {
+        let mut source = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(400, 200, Rgb([255, 0, 0])))
+            .write_to(&mut source, ImageOutputFormat::Png)
+            .unwrap();
+        let source = source.into_inner();
+        let context = AssetContext::new("wide.png", Some(0), None);
+
+        let size_with = |resize| {
+            let options = ImageOptions {
+                resize,
+                ..ImageOptions::new(100, 100)
+            };
+            let processed = process(&source, &options, &context).unwrap();
+            assert_eq!(processed.format, BinaryFormat::Jpg);
+            assert_eq!(
+                BinaryFormat::sniff(&processed.content),
+                Some(BinaryFormat::Jpg)
+            );
+            let d = processed.dimensions;
+            (d.width, d.height)
+        };
+        assert_eq!(size_with(ResizeMode::Fit), (100, 50));
+        assert_eq!(size_with(ResizeMode::Fill), (100, 100));
+        assert_eq!(size_with(ResizeMode::Letterbox), (100, 100));
+
+        let options = ImageOptions {
+            encoding: ImageEncoding::Png,
+            letterbox_color: [0, 0, 255],
+            resize: ResizeMode::Letterbox,
+            ..ImageOptions::new(100, 100)
+        };
+        let processed = process(&source, &options, &context).unwrap();
+        let decoded = image::load_from_memory(&processed.content)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(decoded.get_pixel(50, 10), &Rgb([0, 0, 255]));
+        assert_eq!(decoded.get_pixel(50, 50), &Rgb([255, 0, 0]));
+
+        assert!(matches!(
+            process(b"image 00", &options, &context),
+            Err(FlipbookError::ImageProcessing { .. })
+        ));
+    }
+
+    #[test]
+    fn miniature_drops_metadata() {
+        let mut jpeg = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 40, Rgb([0, 0, 200])))
+            .write_to(&mut jpeg, ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        let mut jpeg = jpeg.into_inner();
+        // An APP1 segment right after the start of image, as cameras write it
+        let exif = b"Exif\0\0GPS of the illustrator";
+        let mut segment = vec![0xff, 0xe1];
+        segment.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(exif);
+        jpeg.splice(2..2, segment);
+        let context = AssetContext::new("cover.jpg", None, None);
+
+        let options = MiniatureOptions {
+            max_width: 100,
+            max_height: 100,
+            quality: 60,
+        };
+        let miniature = miniature(&jpeg, &options, &context).unwrap();
+        assert_eq!(BinaryFormat::sniff(&miniature), Some(BinaryFormat::Jpg));
+        assert!(!miniature.windows(4).any(|w| w == b"Exif"));
+        let decoded = image::load_from_memory(&miniature).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (40, 40));
+    }
+
+    #[test]
+    fn miniature_from_the_first_page() {
+        use crate::compile::{compile_with_options, CompileOptions};
+        use crate::reader::PackageReader;
+        use crate::test_utils::{
+            compile_source, path_binary, path_metadata, sample_options, sample_source, scratch_dir,
+        };
+
+        let dir = scratch_dir("miniature");
+        RgbImage::from_pixel(600, 900, Rgb([0, 90, 0]))
+            .save_with_format(dir.join("00.jpg"), image::ImageFormat::Png)
+            .unwrap();
+        let mut source = sample_source(&dir);
+        source.miniature = None;
+        let options = CompileOptions {
+            miniature: MiniatureOptions {
+                max_width: 100,
+                max_height: 100,
+                quality: 60,
+            },
+            ..sample_options()
+        };
+
+        let report =
+            compile_with_options(&source, &path_metadata(&dir), &path_binary(&dir), &options)
+                .unwrap();
+        let reader = PackageReader::open_metadata(&path_metadata(&dir)).unwrap();
+        let miniature = reader.miniature().unwrap();
+        assert_eq!(report.miniature_length, miniature.len() as u64);
+        assert_eq!(BinaryFormat::sniff(&miniature), Some(BinaryFormat::Jpg));
+        let decoded = image::load_from_memory(&miniature).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (67, 100));
+
+        source.pages.clear();
+        assert!(matches!(
+            compile_source(&dir, &source, &options),
+            Err(FlipbookError::NoMiniature)
+        ));
+    }
+
+    #[test]
+    fn image_variants_in_packages_of_their_own() {
+        use crate::compile::{CompileOptions, ImageVariant};
+        use crate::reader::PackageReader;
+        use crate::test_utils::{
+            compile_source, path_binary, path_metadata, sample_options, sample_source, scratch_dir,
+            slice,
+        };
+        use crate::verify::verify;
+
+        let dir = scratch_dir("variants");
+        for page in ["00.jpg", "02.jpg"] {
+            RgbImage::from_pixel(800, 600, Rgb([10, 20, 30]))
+                .save(dir.join(page))
+                .unwrap();
+        }
+        let variant = |name: &str, width, height| ImageVariant {
+            name: name.to_string(),
+            image: ImageOptions::new(width, height),
+        };
+        let mut options = CompileOptions {
+            image_variants: vec![variant("small", 160, 160), variant("large", 320, 320)],
+            variant_packages: true,
+            ..sample_options()
+        };
+        let source = sample_source(&dir);
+        let mut reader = compile_source(&dir, &source, &options).unwrap();
+
+        let package = reader.package().clone();
+        let binary = std::fs::read(path_binary(&dir)).unwrap();
+        let original = std::fs::read(dir.join("00.jpg")).unwrap();
+        assert_eq!(
+            slice(&binary, package.pages[0].image.as_ref().unwrap()),
+            original
+        );
+        assert!(package.pages[1].variants.is_empty());
+
+        assert_eq!(package.variant_packages["small"].url, "out.small.bin");
+        let small = std::fs::read(dir.join("out.small.bin")).unwrap();
+        let fip = &package.pages[2].variants["small"];
+        assert_eq!(
+            fip.dimensions,
+            Some(Dimensions {
+                width: 160,
+                height: 120
+            })
+        );
+        let decoded = image::load_from_memory(slice(&small, fip)).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (160, 120));
+        assert_eq!(package.pages[2].variants["large"].start, 0);
+
+        // Read back, and verified, from the package of the variant
+        let loaded = reader.variant(2, "small").unwrap().unwrap();
+        assert_eq!(loaded.content, slice(&small, fip));
+        assert!(reader.variant(1, "small").unwrap().is_none());
+        assert!(reader.variant(2, "huge").unwrap().is_none());
+        let report = verify(&package, &path_binary(&dir)).unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.variant_packages_match.len(), 2);
+        let mut corrupted = small.clone();
+        corrupted[fip.start as usize] ^= 0xff;
+        std::fs::write(dir.join("out.small.bin"), corrupted).unwrap();
+        let report = verify(&package, &path_binary(&dir)).unwrap();
+        assert!(!report.variant_packages_match["small"]);
+        // Pages 0 and 2 share the same, deduplicated, image
+        let corrupted: Vec<_> = report
+            .corrupted
+            .iter()
+            .map(|c| c.asset.to_string())
+            .collect();
+        assert_eq!(
+            corrupted,
+            ["`small` image of page 0", "`small` image of page 2"]
+        );
+        std::fs::remove_file(dir.join("out.small.bin")).unwrap();
+        assert!(matches!(
+            PackageReader::open_metadata(&path_metadata(&dir)),
+            Err(FlipbookError::PackageRead { .. })
+        ));
+
+        // Or from the main binary package
+        options.variant_packages = false;
+        let mut reader = compile_source(&dir, &source, &options).unwrap();
+        let package = reader.package().clone();
+        assert!(package.variant_packages.is_empty());
+        let loaded = reader.variant(2, "small").unwrap().unwrap();
+        let binary = std::fs::read(path_binary(&dir)).unwrap();
+        assert_eq!(
+            loaded.content,
+            slice(&binary, &package.pages[2].variants["small"])
+        );
+        let report = verify(&package, &path_binary(&dir)).unwrap();
+        assert!(report.is_intact() && report.variant_packages_match.is_empty());
+        // Two pages in three resolutions, one audio
+        assert_eq!(report.verified_assets, 7);
+
+        options.image_variants.push(variant("small", 10, 10));
+        assert!(matches!(
+            compile_source(&dir, &source, &options),
+            Err(FlipbookError::InvalidImageVariant { name, .. }) if name == "small"
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+
+use crate::container;
+use crate::error::{AssetContext, FlipbookError, Result};
+use crate::flipbook::common::FilePath;
+use crate::flipbook::package::{self, Digest, FlipbookPackage, StringID};
+use crate::flipbook::source::FlipbookSource;
+
+use super::build::page_string_id;
+use super::common::{Arguments, Artifacts, Destination};
+use super::options::{
+    AudioOptions, ImageOptions, ImageVariant, MiniatureOptions, MissingBackground,
+};
+use super::report::{BuildStatus, CompileReport};
+use super::writer::{hash_file, to_digest};
+
+/// Extension of the manifest, written next to the metadata or the container
+pub const MANIFEST_EXTENSION: &str = "manifest";
+
+/// What a file looked like when it was compiled
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+struct FileState {
+    size: u64,
+    modified: SystemTime,
+    digest: Digest,
+}
+
+/// The inputs of a compilation and what came out of it. A file that doesn't exist is recorded as
+///   `None`, so it's noticed when it shows up
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(super) struct BuildManifest {
+    compiler_version: String,
+    missing_background: MissingBackground,
+    deduplicate_assets: bool,
+    #[serde(default)]
+    strict_formats: bool,
+    #[serde(default)]
+    images: Option<ImageOptions>,
+    #[serde(default)]
+    image_variants: Vec<ImageVariant>,
+    #[serde(default)]
+    variant_packages: bool,
+    #[serde(default)]
+    audio_options: Option<AudioOptions>,
+    /// Digest of the whole source, texts included
+    source_digest: Digest,
+    #[serde(default)]
+    miniature_options: MiniatureOptions,
+    /// See `FlipbookSource::miniature_source`
+    miniature: (FilePath, Option<FileState>),
+    /// Background of every page
+    backgrounds: Vec<FilePath>,
+    audio: BTreeMap<StringID, FilePath>,
+    /// Every file in `backgrounds` and `audio`
+    assets: BTreeMap<FilePath, Option<FileState>>,
+    /// Timing tracks only reach the metadata
+    #[serde(default)]
+    timings: BTreeMap<FilePath, Option<FileState>>,
+    report: CompileReport,
+}
+
+impl BuildManifest {
+    pub fn report(&self) -> CompileReport {
+        self.report.clone()
+    }
+}
+
+pub(super) fn manifest_path(args: &Arguments) -> PathBuf {
+    match args.destination {
+        Destination::Split { path_metadata, .. } => Path::new(path_metadata),
+        Destination::Container { path } => Path::new(path),
+    }
+    .with_extension(MANIFEST_EXTENSION)
+}
+
+/// `None` when there's no manifest or it can't be read, either way everything is compiled again
+pub(super) fn load_manifest(path: &Path) -> Option<BuildManifest> {
+    let content = std::fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            tracing::warn!("Ignoring manifest `{}`: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub(super) fn save_manifest(path: &Path, manifest: &BuildManifest) -> Result<()> {
+    let f = std::fs::File::create(path).map_err(|source| FlipbookError::OutputWrite {
+        path: path.to_string_lossy().to_string(),
+        source,
+    })?;
+    serde_json::to_writer_pretty(f, manifest).map_err(|source| {
+        FlipbookError::MetadataSerialization {
+            path: path.to_string_lossy().to_string(),
+            source,
+        }
+    })
+}
+
+/// The metadata of the previous compilation, that `BuildStatus::MetadataOnly` starts from
+pub(super) fn previous_metadata(args: &Arguments) -> Result<FlipbookPackage> {
+    match args.destination {
+        Destination::Split { path_metadata, .. } => package::load(path_metadata),
+        Destination::Container { path } => container::open(path).map(|(package, _)| package),
+    }
+}
+
+/// Decides how much of the compilation described by `previous` has to be done again
+pub(super) fn plan(args: &Arguments, previous: &BuildManifest) -> Result<BuildStatus> {
+    let same_options = previous.compiler_version == env!("CARGO_PKG_VERSION")
+        && previous.missing_background == args.options.missing_background
+        && previous.deduplicate_assets == args.options.deduplicate_assets
+        && previous.strict_formats == args.options.strict_formats
+        && previous.images == args.options.images
+        && previous.image_variants == args.options.image_variants
+        && previous.variant_packages == args.options.variant_packages
+        && previous.audio_options == args.options.audio;
+    if !same_options || !outputs_exist(args, &previous.report) {
+        return Ok(BuildStatus::Rebuilt);
+    }
+
+    // The binary package stays valid as long as every page and audio has the same file, as it was
+    if backgrounds(args.source) != previous.backgrounds || audio(args.source) != previous.audio {
+        return Ok(BuildStatus::Rebuilt);
+    }
+    for (path, state) in &previous.assets {
+        if has_changed(path, state)? {
+            tracing::debug!("`{}` changed since the previous compilation", path);
+            return Ok(BuildStatus::Rebuilt);
+        }
+    }
+
+    let (miniature_path, miniature_state) = &previous.miniature;
+    if miniature_path != &args.source.miniature_source().unwrap_or_default()
+        || previous.miniature_options != args.options.miniature
+        || has_changed(miniature_path, miniature_state)?
+        || source_digest(args.source)? != previous.source_digest
+    {
+        return Ok(BuildStatus::MetadataOnly);
+    }
+    for (path, state) in &previous.timings {
+        if has_changed(path, state)? {
+            tracing::debug!("`{}` changed since the previous compilation", path);
+            return Ok(BuildStatus::MetadataOnly);
+        }
+    }
+    Ok(BuildStatus::UpToDate)
+}
+
+/// Records the compilation that produced `compiled`. The digests of the assets come from the
+///   metadata, the files aren't read again
+pub(super) fn manifest_for(args: &Arguments, compiled: &Artifacts) -> Result<BuildManifest> {
+    let metadata = &compiled.metadata;
+    let backgrounds = backgrounds(args.source);
+    let audio = audio(args.source);
+
+    let mut assets = BTreeMap::default();
+    for (path, page) in backgrounds.iter().zip(&metadata.pages) {
+        // A processed image isn't the file it came from
+        let digest = match args.options.images {
+            Some(_) => None,
+            None => page.image.as_ref().and_then(|i| i.digest.clone()),
+        };
+        assets.insert(path.clone(), file_state(path, digest)?);
+    }
+    for (sid, path) in &audio {
+        let digest = match args.options.audio {
+            Some(_) => None,
+            None => metadata.audio.get(sid).and_then(|a| a.digest.clone()),
+        };
+        assets.insert(path.clone(), file_state(path, digest)?);
+    }
+
+    let miniature = args.source.miniature_source().unwrap_or_default();
+    let miniature_state = file_state(&miniature, None)?;
+
+    let mut timings = BTreeMap::default();
+    for (_, _, file) in args.source.pages_timings() {
+        let state = file_state(&file.path, None)?;
+        timings.insert(file.path, state);
+    }
+
+    Ok(BuildManifest {
+        compiler_version: env!("CARGO_PKG_VERSION").to_string(),
+        missing_background: args.options.missing_background,
+        deduplicate_assets: args.options.deduplicate_assets,
+        strict_formats: args.options.strict_formats,
+        images: args.options.images,
+        image_variants: args.options.image_variants.clone(),
+        variant_packages: args.options.variant_packages,
+        audio_options: args.options.audio,
+        source_digest: source_digest(args.source)?,
+        miniature_options: args.options.miniature,
+        miniature: (miniature, miniature_state),
+        backgrounds,
+        audio,
+        assets,
+        timings,
+        report: compiled.report.clone(),
+    })
+}
+
+fn outputs_exist(args: &Arguments, report: &CompileReport) -> bool {
+    match args.destination {
+        Destination::Split {
+            path_metadata,
+            path_binary,
+        } => {
+            let binary_length = std::fs::metadata(path_binary).map(|m| m.len()).ok();
+            let variants_exist = args.options.image_variants.iter().all(|v| {
+                args.path_variant_binary(&v.name)
+                    .is_none_or(|path| path.is_file())
+            });
+            Path::new(path_metadata).is_file()
+                && binary_length == Some(report.binary_package_length)
+                && variants_exist
+        }
+        Destination::Container { path } => Path::new(path).is_file(),
+    }
+}
+
+fn backgrounds(source: &FlipbookSource) -> Vec<FilePath> {
+    source
+        .pages
+        .iter()
+        .map(|p| source.resolve(&p.background.path))
+        .collect()
+}
+
+fn audio(source: &FlipbookSource) -> BTreeMap<StringID, FilePath> {
+    source
+        .pages_audios()
+        .into_iter()
+        .map(|(location, lang, path)| (page_string_id(location, &lang), path))
+        .collect()
+}
+
+/// Through `serde_json::Value` so the keys of the maps always come out sorted
+fn source_digest(flipbook: &FlipbookSource) -> Result<Digest> {
+    let root = flipbook.asset_root.as_ref().map(|root| root.display());
+    let value =
+        serde_json::to_value(flipbook).map_err(|source| FlipbookError::MetadataSerialization {
+            path: root.map(|root| root.to_string()).unwrap_or_default(),
+            source,
+        })?;
+    Ok(to_digest(Sha256::new_with_prefix(value.to_string())))
+}
+
+/// `digest` is computed from the file when not given
+fn file_state(path: &str, digest: Option<Digest>) -> Result<Option<FileState>> {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return Ok(None);
+    };
+    let context = AssetContext::new(path, None, None);
+    let modified = metadata
+        .modified()
+        .map_err(|e| FlipbookError::from_asset_read(context.clone(), e))?;
+    let digest = match digest {
+        Some(digest) => digest,
+        None => hash_file(&context)?,
+    };
+    Ok(Some(FileState {
+        size: metadata.len(),
+        modified,
+        digest,
+    }))
+}
+
+/// Size and modification time tell when a file is untouched, its content is only hashed when
+///   they don't match
+fn has_changed(path: &str, previous: &Option<FileState>) -> Result<bool> {
+    let (current, previous) = match (std::fs::metadata(path).ok(), previous) {
+        (None, None) => return Ok(false),
+        (Some(current), Some(previous)) => (current, previous),
+        _ => return Ok(true),
+    };
+    if current.len() != previous.size {
+        return Ok(true);
+    }
+    if current.modified().ok() == Some(previous.modified) {
+        return Ok(false);
+    }
+    Ok(hash_file(&AssetContext::new(path, None, None))? != previous.digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compile::{compile_with_options, BuildStatus, CompileOptions};
+    use crate::error::FlipbookError;
+    use crate::flipbook::source::FlipbookSource;
+    use crate::test_utils::{
+        path_binary, path_metadata, sample_options, sample_source, scratch_dir, slice,
+    };
+
+    #[test]
+    fn incremental_builds_skip_what_didnt_change() {
+        let dir = scratch_dir("incremental");
+        let mut options = CompileOptions {
+            incremental: true,
+            ..sample_options()
+        };
+        let mut source = sample_source(&dir);
+        let compile_source = |source: &FlipbookSource, options: &CompileOptions| {
+            compile_with_options(source, &path_metadata(&dir), &path_binary(&dir), options)
+                .unwrap()
+                .status
+        };
+
+        assert_eq!(compile_source(&source, &options), BuildStatus::Rebuilt);
+        assert!(dir.join("out.manifest").is_file());
+        assert_eq!(compile_source(&source, &options), BuildStatus::UpToDate);
+
+        // A text changes, the binary package is kept as is
+        let binary = std::fs::read(path_binary(&dir)).unwrap();
+        source.title.0.get_mut("en").unwrap().text = "New title".to_string();
+        assert_eq!(compile_source(&source, &options), BuildStatus::MetadataOnly);
+        let package = crate::flipbook::package::load(&path_metadata(&dir)).unwrap();
+        assert_eq!(package.texts["TITLE_en"], "New title");
+        assert_eq!(
+            slice(&binary, package.pages[2].image.as_ref().unwrap()),
+            b"image 02"
+        );
+        assert_eq!(compile_source(&source, &options), BuildStatus::UpToDate);
+
+        // An asset changes, or the background that was missing shows up
+        std::fs::write(dir.join("02_en.ogg"), "new audio 02").unwrap();
+        assert_eq!(compile_source(&source, &options), BuildStatus::Rebuilt);
+        std::fs::write(dir.join("01.jpg"), "image 01").unwrap();
+        assert_eq!(compile_source(&source, &options), BuildStatus::Rebuilt);
+
+        // Only how strictly formats are checked changes, the fake assets don't pass the check
+        let strict = CompileOptions {
+            strict_formats: true,
+            ..options.clone()
+        };
+        assert!(matches!(
+            compile_with_options(&source, &path_metadata(&dir), &path_binary(&dir), &strict),
+            Err(FlipbookError::UnsupportedFormat { .. })
+        ));
+
+        options.force = true;
+        assert_eq!(compile_source(&source, &options), BuildStatus::Rebuilt);
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+
+/// What to do with a page whose background image can't be found
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MissingBackground {
+    /// Stop the compilation with `FlipbookError::AssetMissing`
+    #[default]
+    Fail,
+    /// Pack a neutral placeholder image in place of the missing one
+    Placeholder,
+    /// Keep the page but without an image, the client decides how to draw it
+    LeaveEmpty,
+}
+
+/// How a background is brought to the target resolution of `ImageOptions`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ResizeMode {
+    /// Scaled to fit inside the target, keeping its aspect ratio: one side may come out shorter
+    #[default]
+    Fit,
+    /// Scaled to cover the whole target, keeping its aspect ratio, and cropped around the center
+    Fill,
+    /// Like `Fit`, centered on a canvas of exactly the target size
+    Letterbox,
+}
+
+/// Format the backgrounds are re-encoded to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ImageEncoding {
+    /// `quality` from 1 to 100
+    Jpg {
+        quality: u8,
+    },
+    Png,
+}
+
+impl Default for ImageEncoding {
+    fn default() -> Self {
+        Self::Jpg { quality: 85 }
+    }
+}
+
+/// The image stage of the compilation: every background is decoded, resized and encoded again,
+///   which also drops EXIF and any other metadata the file carried
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ImageOptions {
+    pub width: u32,
+    pub height: u32,
+    pub resize: ResizeMode,
+    pub encoding: ImageEncoding,
+    /// Color of the bands added by `ResizeMode::Letterbox`, as RGB
+    pub letterbox_color: [u8; 3],
+}
+
+impl ImageOptions {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            resize: ResizeMode::default(),
+            encoding: ImageEncoding::default(),
+            letterbox_color: [0, 0, 0],
+        }
+    }
+}
+
+/// How the miniature embedded in the metadata is prepared. It's part of every catalogue listing so
+///   it's kept small
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MiniatureOptions {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// JPEG quality, from 1 to 100
+    pub quality: u8,
+}
+
+impl Default for MiniatureOptions {
+    fn default() -> Self {
+        Self {
+            max_width: 256,
+            max_height: 256,
+            quality: 75,
+        }
+    }
+}
+
+/// Another resolution of every background, see `PageInPackage::variants`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ImageVariant {
+    /// Unique among the variants, it's also part of the file name of its binary package
+    pub name: String,
+    pub image: ImageOptions,
+}
+
+/// Format the audio is encoded to. There's no pure Rust encoder for the compressed ones, yet
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AudioEncoding {
+    /// 16 bits PCM
+    #[default]
+    Wav,
+}
+
+/// The audio stage of the compilation: every audio is decoded, trimmed, brought to the same
+///   loudness and encoded again, as `encoding` says
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AudioOptions {
+    /// RMS level the audio is brought to, in dBFS, never letting the peaks go over -1 dBFS.
+    ///   `None` keeps the level it has
+    pub target_loudness_db: Option<f32>,
+    /// Leading and trailing audio quieter than this, in dBFS, is removed. `None` keeps it
+    pub trim_silence_db: Option<f32>,
+    /// `None` keeps the format of the source. Only a WAV can be trimmed or normalized that way,
+    ///   anything else fails with `AudioEncodingUnavailable` instead of becoming a WAV unasked
+    pub encoding: Option<AudioEncoding>,
+}
+
+impl Default for AudioOptions {
+    fn default() -> Self {
+        Self {
+            target_loudness_db: Some(-20.0),
+            trim_silence_db: Some(-50.0),
+            encoding: Some(AudioEncoding::default()),
+        }
+    }
+}
+
+/// Knobs of the compilation, `CompileOptions::default()` is what `compile` uses
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    pub missing_background: MissingBackground,
+    /// Store a single copy of the assets that are the same file or have the same content
+    pub deduplicate_assets: bool,
+    /// Keep a manifest of the inputs next to the outputs and, when compiling again, skip whatever
+    ///   they say is up to date. See `BuildStatus`
+    pub incremental: bool,
+    /// With `incremental`, ignore the previous manifest and compile everything again
+    pub force: bool,
+    /// Fail on assets whose content doesn't match their extension, or can't be recognized,
+    ///   instead of warning
+    pub strict_formats: bool,
+    /// `None` packs the backgrounds byte for byte, as they are
+    pub images: Option<ImageOptions>,
+    pub miniature: MiniatureOptions,
+    /// Extra resolutions of every background, on top of the one in `PageInPackage::image`
+    pub image_variants: Vec<ImageVariant>,
+    /// Write every variant into a binary package of its own, next to the main one. Containers
+    ///   ignore it, they're a single file
+    pub variant_packages: bool,
+    /// `None` packs the audio byte for byte, as it is
+    pub audio: Option<AudioOptions>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            missing_background: MissingBackground::default(),
+            deduplicate_assets: true,
+            incremental: false,
+            force: false,
+            strict_formats: false,
+            images: None,
+            miniature: MiniatureOptions::default(),
+            image_variants: vec![],
+            variant_packages: false,
+            audio: None,
+        }
+    }
+}
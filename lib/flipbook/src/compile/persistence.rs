@@ -1,17 +1,29 @@
-use std::io::Write;
+use crate::container;
+use crate::error::{FlipbookError, Result};
 
-use anyhow::Result;
+use super::common::{Arguments, Artifacts, Destination};
 
-use super::common::Artifacts;
-
-pub fn to_disk(compiled: &Artifacts, path_metadata: &str, path_binary: &str) -> Result<()> {
-    {
-        let f_metadata = std::fs::File::create(path_metadata)?;
-        serde_json::to_writer_pretty(f_metadata, &compiled.metadata)?;
-    }
-    {
-        let mut f_bin = std::fs::File::create(path_binary)?;
-        f_bin.write_all(&compiled.binary_package)?;
+/// Only the metadata is left to persist, the binary package is written while building
+pub fn to_disk(compiled: &Artifacts, args: &Arguments) -> Result<()> {
+    match args.destination {
+        Destination::Split { path_metadata, .. } => {
+            let f_metadata = std::fs::File::create(path_metadata).map_err(|source| {
+                FlipbookError::OutputWrite {
+                    path: path_metadata.to_string(),
+                    source,
+                }
+            })?;
+            serde_json::to_writer_pretty(f_metadata, &compiled.metadata).map_err(|source| {
+                FlipbookError::MetadataSerialization {
+                    path: path_metadata.to_string(),
+                    source,
+                }
+            })
+        }
+        Destination::Container { path } => container::finish(
+            path,
+            compiled.report.binary_package_length,
+            &compiled.metadata,
+        ),
     }
-    Ok(())
 }
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+/// How much work a compilation took, see `CompileOptions::incremental`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BuildStatus {
+    /// Binary package and metadata written from scratch
+    #[default]
+    Rebuilt,
+    /// The assets didn't change, only the metadata was written again
+    MetadataOnly,
+    /// Nothing changed since the previous compilation, nothing was written
+    UpToDate,
+}
+
+/// Summary of a compilation, for whoever invoked it to log or act upon
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CompileReport {
+    pub binary_package_length: u64,
+    /// Assets referenced more than once but stored a single time in the binary package
+    pub reused_assets: usize,
+    /// Bytes the binary package would have on top of `binary_package_length` without deduplication
+    pub bytes_saved: u64,
+    /// Bytes of the miniature embedded in the metadata, before encoding it as base64
+    #[serde(default)]
+    pub miniature_length: u64,
+    #[serde(default)]
+    pub status: BuildStatus,
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::PathBuf;
+
+use sha2::{Digest as _, Sha256};
+
+use crate::error::{AssetContext, FlipbookError, Result};
+use crate::flipbook::format::BinaryFormat;
+use crate::flipbook::package::{Digest, FilePositionInPackage};
+
+/// Size of the chunks moved from an asset into the binary package
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Formats a finished SHA-256 the way `Digest` expects it
+pub fn to_digest(hasher: Sha256) -> Digest {
+    format!("{:x}", hasher.finalize())
+}
+
+/// What's known of the binary package once every asset has been written
+pub struct BinaryPackageSummary {
+    pub length: u64,
+    pub digest: Digest,
+    /// Assets that pointed at an already stored copy instead of being written again
+    pub reused_assets: usize,
+    pub bytes_saved: u64,
+}
+
+/// Streams assets into the binary package as they're found, keeping track of their offsets and
+///   digests. At no point a whole asset is held in memory, only a chunk of it
+/// When deduplicating, an asset already stored (same file or same content) isn't written again:
+///   the returned position points at the stored copy
+pub struct PackageWriter<W: Write> {
+    output: W,
+    /// Used to give context to the errors writing into `output`
+    output_path: String,
+    position: u64,
+    package_hasher: Sha256,
+
+    deduplicate: bool,
+    stored_by_path: HashMap<PathBuf, FilePositionInPackage>,
+    stored_by_digest: HashMap<Digest, FilePositionInPackage>,
+    reused_assets: usize,
+    bytes_saved: u64,
+}
+
+impl PackageWriter<BufWriter<File>> {
+    pub fn create(path: &str, deduplicate: bool) -> Result<Self> {
+        let f = File::create(path).map_err(|source| FlipbookError::OutputWrite {
+            path: path.to_string(),
+            source,
+        })?;
+        Ok(Self::new(BufWriter::new(f), path, deduplicate))
+    }
+}
+
+impl<W: Write> PackageWriter<W> {
+    pub fn new(output: W, output_path: &str, deduplicate: bool) -> Self {
+        Self {
+            output,
+            output_path: output_path.to_string(),
+            position: 0,
+            package_hasher: Sha256::new(),
+            deduplicate,
+            stored_by_path: HashMap::default(),
+            stored_by_digest: HashMap::default(),
+            reused_assets: 0,
+            bytes_saved: 0,
+        }
+    }
+
+    fn output_error(&self, source: std::io::Error) -> FlipbookError {
+        FlipbookError::OutputWrite {
+            path: self.output_path.clone(),
+            source,
+        }
+    }
+
+    /// Points at a copy already in the package, keeping the format the asset is referenced with
+    fn reuse(
+        &mut self,
+        stored: &FilePositionInPackage,
+        format: BinaryFormat,
+    ) -> FilePositionInPackage {
+        self.reused_assets += 1;
+        self.bytes_saved += stored.length;
+        FilePositionInPackage {
+            format,
+            ..stored.clone()
+        }
+    }
+
+    fn find_by_digest(
+        &mut self,
+        digest: &Digest,
+        format: BinaryFormat,
+    ) -> Option<FilePositionInPackage> {
+        if !self.deduplicate {
+            return None;
+        }
+        let stored = self.stored_by_digest.get(digest)?.clone();
+        Some(self.reuse(&stored, format))
+    }
+
+    fn remember(&mut self, path: Option<PathBuf>, fip: &FilePositionInPackage) {
+        if !self.deduplicate {
+            return;
+        }
+        if let Some(digest) = &fip.digest {
+            self.stored_by_digest
+                .entry(digest.clone())
+                .or_insert_with(|| fip.clone());
+        }
+        if let Some(path) = path {
+            self.stored_by_path.insert(path, fip.clone());
+        }
+    }
+
+    pub fn append_bytes(
+        &mut self,
+        format: BinaryFormat,
+        content: &[u8],
+    ) -> Result<FilePositionInPackage> {
+        let digest = to_digest(Sha256::new_with_prefix(content));
+        if let Some(fip) = self.find_by_digest(&digest, format) {
+            return Ok(fip);
+        }
+
+        self.output
+            .write_all(content)
+            .map_err(|e| self.output_error(e))?;
+        self.package_hasher.update(content);
+
+        let fip = FilePositionInPackage {
+            format,
+            start: self.position,
+            length: content.len() as u64,
+            digest: Some(digest),
+            dimensions: None,
+            audio_properties: None,
+        };
+        self.position += fip.length;
+        self.remember(None, &fip);
+        Ok(fip)
+    }
+
+    pub fn append_file(
+        &mut self,
+        format: BinaryFormat,
+        context: AssetContext,
+    ) -> Result<FilePositionInPackage> {
+        if !self.deduplicate {
+            return self.copy_file(format, context);
+        }
+
+        let canonical = std::fs::canonicalize(&context.path)
+            .map_err(|e| FlipbookError::from_asset_read(context.clone(), e))?;
+        if let Some(stored) = self.stored_by_path.get(&canonical).cloned() {
+            return Ok(self.reuse(&stored, format));
+        }
+
+        // A first pass over the file to know if its content is already stored
+        let digest = hash_file(&context)?;
+        if let Some(fip) = self.find_by_digest(&digest, format) {
+            self.stored_by_path.insert(canonical, fip.clone());
+            return Ok(fip);
+        }
+
+        let fip = self.copy_file(format, context)?;
+        self.remember(Some(canonical), &fip);
+        Ok(fip)
+    }
+
+    fn copy_file(
+        &mut self,
+        format: BinaryFormat,
+        context: AssetContext,
+    ) -> Result<FilePositionInPackage> {
+        let mut input = match File::open(&context.path) {
+            Ok(f) => f,
+            Err(e) => return Err(FlipbookError::from_asset_read(context, e)),
+        };
+
+        let start = self.position;
+        let mut asset_hasher = Sha256::new();
+        let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+        loop {
+            let read = match input.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(FlipbookError::from_asset_read(context, e)),
+            };
+            self.output
+                .write_all(&buffer[..read])
+                .map_err(|e| self.output_error(e))?;
+            self.package_hasher.update(&buffer[..read]);
+            asset_hasher.update(&buffer[..read]);
+            self.position += read as u64;
+        }
+
+        Ok(FilePositionInPackage {
+            format,
+            start,
+            length: self.position - start,
+            digest: Some(to_digest(asset_hasher)),
+            dimensions: None,
+            audio_properties: None,
+        })
+    }
+
+    /// Flushes whatever is pending, returning what's known of the whole binary package
+    pub fn finish(mut self) -> Result<BinaryPackageSummary> {
+        self.output.flush().map_err(|e| self.output_error(e))?;
+        Ok(BinaryPackageSummary {
+            length: self.position,
+            digest: to_digest(self.package_hasher),
+            reused_assets: self.reused_assets,
+            bytes_saved: self.bytes_saved,
+        })
+    }
+}
+
+pub fn hash_file(context: &AssetContext) -> Result<Digest> {
+    let read_error = |e| FlipbookError::from_asset_read(context.clone(), e);
+    let mut input = File::open(&context.path).map_err(read_error)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut input, &mut hasher).map_err(read_error)?;
+    Ok(to_digest(hasher))
+}
@@ -0,0 +1,219 @@
+// The `container` module defines the single file alternative to the metadata + binary package
+//   pair: a compiled flipbook as one artifact that can be moved, renamed and cached on its own.
+//
+// Layout, every integer is little endian
+//   [ Header (48 bytes) | Asset section | Metadata section ]
+//   Header:
+//     magic            8 bytes, `CONTAINER_MAGIC`
+//     version          u32, `CONTAINER_VERSION`
+//     reserved         u32, zero
+//     assets offset    u64
+//     assets length    u64
+//     metadata offset  u64
+//     metadata length  u64
+//   Asset section: exactly what the binary package of the split format would contain
+//   Metadata section: the package metadata as JSON. It's the index of the asset section, every
+//     `FilePositionInPackage` is relative to the start of the asset section and
+//     `binary_package_url` is left empty
+// The metadata goes last so the assets can be streamed while compiling and the header patched at
+//   the end, once every length is known.
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::error::{FlipbookError, Result};
+use crate::flipbook::package::{self, FlipbookPackage};
+use crate::reader::AssetReader;
+
+pub const CONTAINER_MAGIC: &[u8; 8] = b"LBFLIPBK";
+pub const CONTAINER_VERSION: u32 = 1;
+pub const HEADER_LENGTH: u64 = 48;
+
+/// Extension used for containers, next to the split `.json` + `.bin`
+pub const CONTAINER_EXTENSION: &str = "flipbook";
+
+/// Where the sections of a container are
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Header {
+    pub assets_offset: u64,
+    pub assets_length: u64,
+    pub metadata_offset: u64,
+    pub metadata_length: u64,
+}
+
+impl Header {
+    pub fn to_bytes(self) -> [u8; HEADER_LENGTH as usize] {
+        let mut bytes = [0u8; HEADER_LENGTH as usize];
+        bytes[0..8].copy_from_slice(CONTAINER_MAGIC);
+        bytes[8..12].copy_from_slice(&CONTAINER_VERSION.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.assets_offset.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.assets_length.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.metadata_offset.to_le_bytes());
+        bytes[40..48].copy_from_slice(&self.metadata_length.to_le_bytes());
+        bytes
+    }
+
+    /// `path` only gives context to the errors
+    pub fn from_bytes(bytes: &[u8; HEADER_LENGTH as usize], path: &str) -> Result<Self> {
+        let invalid = |reason: &str| FlipbookError::InvalidContainer {
+            path: path.to_string(),
+            reason: reason.to_string(),
+        };
+        let u64_at = |at: usize| {
+            let mut le = [0u8; 8];
+            le.copy_from_slice(&bytes[at..at + 8]);
+            u64::from_le_bytes(le)
+        };
+
+        if &bytes[0..8] != CONTAINER_MAGIC {
+            return Err(invalid("not a flipbook container"));
+        }
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&bytes[8..12]);
+        if u32::from_le_bytes(version) != CONTAINER_VERSION {
+            return Err(invalid("unknown container version"));
+        }
+
+        Ok(Self {
+            assets_offset: u64_at(16),
+            assets_length: u64_at(24),
+            metadata_offset: u64_at(32),
+            metadata_length: u64_at(40),
+        })
+    }
+}
+
+/// Opens a container, returning its metadata (upgraded to the newest version) and a reader for
+///   its assets
+pub fn open(path: &str) -> Result<(FlipbookPackage, AssetReader)> {
+    let read_error = |source| FlipbookError::PackageRead {
+        path: path.to_string(),
+        source,
+    };
+    let mut file = File::open(path).map_err(read_error)?;
+    let file_length = file.metadata().map_err(read_error)?.len();
+
+    let mut header_bytes = [0u8; HEADER_LENGTH as usize];
+    file.read_exact(&mut header_bytes).map_err(read_error)?;
+    let header = Header::from_bytes(&header_bytes, path)?;
+
+    let within_file = |offset: u64, length: u64| {
+        let end = offset.checked_add(length);
+        offset >= HEADER_LENGTH && matches!(end, Some(end) if end <= file_length)
+    };
+    if !within_file(header.assets_offset, header.assets_length)
+        || !within_file(header.metadata_offset, header.metadata_length)
+    {
+        return Err(FlipbookError::InvalidContainer {
+            path: path.to_string(),
+            reason: "sections out of the file".to_string(),
+        });
+    }
+
+    file.seek(SeekFrom::Start(header.metadata_offset))
+        .map_err(read_error)?;
+    let mut metadata = String::new();
+    (&mut file)
+        .take(header.metadata_length)
+        .read_to_string(&mut metadata)
+        .map_err(read_error)?;
+    let package = package::from_json(&metadata, path)?;
+
+    let reader = AssetReader::new(file, path, header.assets_offset, header.assets_length);
+    Ok((package, reader))
+}
+
+/// Creates a container leaving room for its header, the asset section starts right after it
+pub(crate) fn create(path: &str) -> Result<File> {
+    let output_error = |source| FlipbookError::OutputWrite {
+        path: path.to_string(),
+        source,
+    };
+    let mut file = File::create(path).map_err(output_error)?;
+    file.write_all(&Header::default().to_bytes())
+        .map_err(output_error)?;
+    Ok(file)
+}
+
+/// Closes a container whose asset section, `assets_length` bytes, is already written: appends the
+///   metadata section and fills the header
+pub(crate) fn finish(path: &str, assets_length: u64, metadata: &FlipbookPackage) -> Result<()> {
+    let output_error = |source| FlipbookError::OutputWrite {
+        path: path.to_string(),
+        source,
+    };
+    let metadata = serde_json::to_vec_pretty(metadata).map_err(|source| {
+        FlipbookError::MetadataSerialization {
+            path: path.to_string(),
+            source,
+        }
+    })?;
+
+    let header = Header {
+        assets_offset: HEADER_LENGTH,
+        assets_length,
+        metadata_offset: HEADER_LENGTH + assets_length,
+        metadata_length: metadata.len() as u64,
+    };
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(output_error)?;
+    file.seek(SeekFrom::Start(header.metadata_offset))
+        .map_err(output_error)?;
+    file.write_all(&metadata).map_err(output_error)?;
+    file.set_len(header.metadata_offset + header.metadata_length)
+        .map_err(output_error)?;
+    file.seek(SeekFrom::Start(0)).map_err(output_error)?;
+    file.write_all(&header.to_bytes()).map_err(output_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::compile_container;
+    use crate::flipbook::package::FilePositionInPackage;
+    use crate::test_utils::{sample_options, sample_source, scratch_dir};
+
+    #[test]
+    fn container_round_trip() {
+        let dir = scratch_dir("container");
+        let path = dir.join("book.flipbook");
+        let report = compile_container(
+            &sample_source(&dir),
+            path.to_str().unwrap(),
+            &sample_options(),
+        )
+        .unwrap();
+
+        let (package, mut assets) = open(path.to_str().unwrap()).unwrap();
+        assert_eq!(assets.len(), report.binary_package_length);
+        assert!(package.binary_package_url.is_empty());
+
+        let image = package.pages[2].image.as_ref().unwrap();
+        assert_eq!(assets.read(image).unwrap(), b"image 02");
+        let audio = &package.audio[&package.pages[2].audio["en"]];
+        assert_eq!(assets.read(audio).unwrap(), b"audio 02");
+
+        let beyond = FilePositionInPackage {
+            start: assets.len(),
+            ..image.clone()
+        };
+        assert!(matches!(
+            assets.read(&beyond),
+            Err(FlipbookError::AssetOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let dir = scratch_dir("container-invalid");
+        let path = dir.join("00.jpg");
+        std::fs::write(&path, [0u8; HEADER_LENGTH as usize]).unwrap();
+        assert!(matches!(
+            open(path.to_str().unwrap()),
+            Err(FlipbookError::InvalidContainer { .. })
+        ));
+    }
+}
@@ -0,0 +1,375 @@
+// The `diff` module compares two compiled flipbooks the way a reader would notice: languages and
+//   their fallbacks, pages, texts and where their blocks are drawn, timings and the content of
+//   every asset, variants included. Offsets and digests aren't compared, two packages with the
+//   same assets in a different order are the same book.
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::flipbook::common::{LanguageCode, RawString};
+use crate::flipbook::package::{BlockInPackage, StringID};
+use crate::reader::PackageReader;
+use crate::verify::AssetId;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Difference {
+    Languages {
+        left: Vec<LanguageCode>,
+        right: Vec<LanguageCode>,
+    },
+    DefaultLanguage {
+        left: LanguageCode,
+        right: LanguageCode,
+    },
+    /// `None` when the language has no fallbacks in one of the sides
+    Fallbacks {
+        language: LanguageCode,
+        left: Option<Vec<LanguageCode>>,
+        right: Option<Vec<LanguageCode>>,
+    },
+    PageCount {
+        left: usize,
+        right: usize,
+    },
+    Miniature,
+    /// `None` when the text only exists in one of the sides
+    Text {
+        string_id: StringID,
+        left: Option<RawString>,
+        right: Option<RawString>,
+    },
+    /// Same plain text, or missing, with different markup
+    Markup {
+        string_id: StringID,
+        left: Option<RawString>,
+        right: Option<RawString>,
+    },
+    /// A block missing in one of the sides, or drawn somewhere else or differently. Its texts are
+    ///   compared as any other
+    TextBlock {
+        page: usize,
+        block: usize,
+    },
+    /// The timing tracks of a text, missing in one of the sides or with other cues
+    Timings {
+        string_id: StringID,
+    },
+    /// Missing in one of the sides, or different format or content
+    Asset {
+        asset: AssetId,
+    },
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difference::Languages { left, right } => {
+                write!(f, "languages: {left:?} -> {right:?}")
+            }
+            Difference::DefaultLanguage { left, right } => {
+                write!(f, "default language: `{left}` -> `{right}`")
+            }
+            Difference::Fallbacks {
+                language,
+                left,
+                right,
+            } => write!(f, "fallbacks of `{language}`: {left:?} -> {right:?}"),
+            Difference::PageCount { left, right } => write!(f, "pages: {left} -> {right}"),
+            Difference::Miniature => write!(f, "miniature"),
+            Difference::Text {
+                string_id,
+                left,
+                right,
+            } => write!(f, "text `{string_id}`: {left:?} -> {right:?}"),
+            Difference::Markup {
+                string_id,
+                left,
+                right,
+            } => write!(f, "markup `{string_id}`: {left:?} -> {right:?}"),
+            Difference::TextBlock { page, block } => {
+                write!(f, "text block {block} of page {page}")
+            }
+            Difference::Timings { string_id } => write!(f, "timings `{string_id}`"),
+            Difference::Asset { asset } => write!(f, "asset: {asset}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PackageDiff {
+    pub differences: Vec<Difference>,
+}
+
+impl PackageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// Compares `left` against `right`, reading every asset of both
+pub fn diff(left: &mut PackageReader, right: &mut PackageReader) -> Result<PackageDiff> {
+    let mut differences = vec![];
+
+    if left.languages() != right.languages() {
+        differences.push(Difference::Languages {
+            left: left.languages().to_vec(),
+            right: right.languages().to_vec(),
+        });
+    }
+    if left.default_language() != right.default_language() {
+        differences.push(Difference::DefaultLanguage {
+            left: left.default_language().to_string(),
+            right: right.default_language().to_string(),
+        });
+    }
+    let left_fallbacks = &left.package().fallbacks;
+    let right_fallbacks = &right.package().fallbacks;
+    let languages: BTreeSet<&LanguageCode> = left_fallbacks
+        .keys()
+        .chain(right_fallbacks.keys())
+        .collect();
+    for language in languages {
+        let l = left_fallbacks.get(language);
+        let r = right_fallbacks.get(language);
+        if l != r {
+            differences.push(Difference::Fallbacks {
+                language: language.clone(),
+                left: l.cloned(),
+                right: r.cloned(),
+            });
+        }
+    }
+    if left.page_count() != right.page_count() {
+        differences.push(Difference::PageCount {
+            left: left.page_count(),
+            right: right.page_count(),
+        });
+    }
+    if left.miniature()? != right.miniature()? {
+        differences.push(Difference::Miniature);
+    }
+
+    let string_ids: BTreeSet<&StringID> = left
+        .package()
+        .texts
+        .keys()
+        .chain(right.package().texts.keys())
+        .collect();
+    for string_id in string_ids {
+        let l = left.package().texts.get(string_id);
+        let r = right.package().texts.get(string_id);
+        if l != r {
+            differences.push(Difference::Text {
+                string_id: string_id.clone(),
+                left: l.cloned(),
+                right: r.cloned(),
+            });
+        }
+    }
+
+    // A change in the markup changes the plain text too most of the time, that's reported above
+    let markup_ids: BTreeSet<&StringID> = left
+        .package()
+        .markup
+        .keys()
+        .chain(right.package().markup.keys())
+        .collect();
+    for string_id in markup_ids {
+        let l = left.package().markup.get(string_id);
+        let r = right.package().markup.get(string_id);
+        if l != r && left.package().texts.get(string_id) == right.package().texts.get(string_id) {
+            differences.push(Difference::Markup {
+                string_id: string_id.clone(),
+                left: l.cloned(),
+                right: r.cloned(),
+            });
+        }
+    }
+
+    let timing_ids: BTreeSet<&StringID> = left
+        .package()
+        .timings
+        .keys()
+        .chain(right.package().timings.keys())
+        .collect();
+    for string_id in timing_ids {
+        if left.package().timings.get(string_id) != right.package().timings.get(string_id) {
+            differences.push(Difference::Timings {
+                string_id: string_id.clone(),
+            });
+        }
+    }
+
+    for page in 0..left.page_count().max(right.page_count()) {
+        let blocks = |reader: &PackageReader| {
+            let blocks = reader.page(page).map(|p| p.blocks.as_slice());
+            let layout = |b: &BlockInPackage| (b.rect, b.align, b.font_size);
+            blocks
+                .unwrap_or_default()
+                .iter()
+                .map(layout)
+                .collect::<Vec<_>>()
+        };
+        let (l, r) = (blocks(left), blocks(right));
+        for block in 0..l.len().max(r.len()) {
+            if l.get(block) != r.get(block) {
+                differences.push(Difference::TextBlock { page, block });
+            }
+        }
+    }
+
+    for page in 0..left.page_count().max(right.page_count()) {
+        if left.image(page)? != right.image(page)? {
+            differences.push(Difference::Asset {
+                asset: AssetId::PageImage { page },
+            });
+        }
+        let variants = |reader: &PackageReader| {
+            let variants = reader.page(page).map(|p| p.variants.keys().cloned());
+            variants.into_iter().flatten().collect::<BTreeSet<_>>()
+        };
+        for name in variants(left).union(&variants(right)) {
+            if left.variant(page, name)? != right.variant(page, name)? {
+                differences.push(Difference::Asset {
+                    asset: AssetId::Variant {
+                        page,
+                        name: name.clone(),
+                    },
+                });
+            }
+        }
+    }
+
+    let audio_ids: BTreeSet<StringID> = left
+        .package()
+        .audio
+        .keys()
+        .chain(right.package().audio.keys())
+        .cloned()
+        .collect();
+    for string_id in audio_ids {
+        let l = left.package().audio.get(&string_id).cloned();
+        let r = right.package().audio.get(&string_id).cloned();
+        let l = l.map(|fip| left.load(&fip)).transpose()?;
+        let r = r.map(|fip| right.load(&fip)).transpose()?;
+        if l != r {
+            differences.push(Difference::Asset {
+                asset: AssetId::Audio { string_id },
+            });
+        }
+    }
+
+    Ok(PackageDiff { differences })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flipbook::source::FlipbookSource;
+    use crate::test_utils::{
+        compile_sample, compile_source, sample_options, sample_source, scratch_dir,
+    };
+
+    #[test]
+    fn finds_changed_texts_and_assets() {
+        let mut left = compile_sample(&scratch_dir("diff-left"), &sample_options());
+        let mut same = compile_sample(&scratch_dir("diff-same"), &sample_options());
+        assert!(diff(&mut left, &mut same).unwrap().is_empty());
+
+        let dir = scratch_dir("diff-right");
+        let mut source = sample_source(&dir);
+        let text = source.pages[1].text.as_mut().unwrap();
+        text.0.get_mut("en").unwrap().text = "uno".to_string();
+        source.pages[0].background = source.pages[2].background.clone();
+        let mut right = compile_source(&dir, &source, &sample_options()).unwrap();
+
+        let differences = diff(&mut left, &mut right).unwrap().differences;
+        assert_eq!(
+            differences,
+            vec![
+                Difference::Text {
+                    string_id: "PAGE_1_en".to_string(),
+                    left: Some("one".to_string()),
+                    right: Some("uno".to_string()),
+                },
+                Difference::Asset {
+                    asset: AssetId::PageImage { page: 0 }
+                },
+            ]
+        );
+        assert_eq!(differences[1].to_string(), "asset: image of page 0");
+    }
+
+    #[test]
+    fn finds_changed_layout_timings_fallbacks_and_variants() {
+        use crate::compile::{CompileOptions, ImageOptions, ImageVariant};
+
+        let compile = |name: &str, edit: &dyn Fn(&mut FlipbookSource, &mut CompileOptions)| {
+            let dir = scratch_dir(name);
+            for (file, colour) in [("00.jpg", [10, 20, 30]), ("02.jpg", [30, 20, 10])] {
+                image::RgbImage::from_pixel(80, 60, image::Rgb(colour))
+                    .save(dir.join(file))
+                    .unwrap();
+            }
+            let vtt = "WEBVTT\n\n00:00.000 --> 00:00.500\ntwo\n";
+            std::fs::write(dir.join("02_en.vtt"), vtt).unwrap();
+            let mut source = sample_source(&dir);
+            source.languages.push("sv".to_string());
+            source.fallbacks = [("sv".to_string(), vec!["en".to_string()])].into();
+            let timing = dir.join("02_en.vtt").to_str().unwrap().to_string();
+            source.pages[2]
+                .text
+                .as_mut()
+                .unwrap()
+                .0
+                .get_mut("en")
+                .unwrap()
+                .timings = serde_json::from_value(serde_json::json!([{ "path": timing }])).unwrap();
+            source.pages[2].blocks = serde_json::from_value(serde_json::json!([{
+                "rect": { "x": 0.1, "y": 0.1, "width": 0.5, "height": 0.2 },
+                "text": { "en": { "text": "block" } }
+            }]))
+            .unwrap();
+            let mut options = CompileOptions {
+                image_variants: vec![ImageVariant {
+                    name: "small".to_string(),
+                    image: ImageOptions::new(40, 40),
+                }],
+                ..sample_options()
+            };
+            edit(&mut source, &mut options);
+            compile_source(&dir, &source, &options).unwrap()
+        };
+
+        let mut left = compile("diff-layout-left", &|_, _| {});
+        let mut right = compile("diff-layout-right", &|source, options| {
+            source.fallbacks.clear();
+            source.pages[2].blocks[0].rect.x = 0.2;
+            let vtt = "WEBVTT\n\n00:00.000 --> 00:00.700\ntwo\n";
+            std::fs::write(
+                &source.pages[2].text.as_ref().unwrap().0["en"].timings[0].path,
+                vtt,
+            )
+            .unwrap();
+            options.image_variants[0].image = ImageOptions::new(20, 20);
+        });
+        let differences: Vec<String> = diff(&mut left, &mut right)
+            .unwrap()
+            .differences
+            .iter()
+            .map(Difference::to_string)
+            .collect();
+        assert_eq!(
+            differences,
+            [
+                "fallbacks of `sv`: Some([\"en\"]) -> None",
+                "timings `PAGE_2_en`",
+                "text block 0 of page 2",
+                "asset: `small` image of page 0",
+                "asset: `small` image of page 2",
+            ]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+// Every fallible operation of the library reports through `FlipbookError`, so whoever embeds it
+//   can tell an author's mistake (a missing asset, a format the client can't play, a broken
+//   source JSON) apart from an environment problem (the disk refusing to write the output).
+use std::fmt::Display;
+
+use thiserror::Error;
+
+use crate::flipbook::common::{FilePath, LanguageCode, MetadataVersion};
+use crate::flipbook::format::BinaryFormat;
+use crate::flipbook::language::LanguageTagError;
+use crate::flipbook::markup::MarkupError;
+use crate::flipbook::package::StringID;
+use crate::flipbook::timing::TimingParseError;
+
+pub type Result<T> = std::result::Result<T, FlipbookError>;
+
+/// Where in the source flipbook an asset was referenced from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssetContext {
+    pub path: FilePath,
+    /// `None` for assets outside the pages, like the miniature
+    pub page: Option<usize>,
+    pub language: Option<LanguageCode>,
+}
+
+impl AssetContext {
+    pub fn new(path: &str, page: Option<usize>, language: Option<&str>) -> Self {
+        Self {
+            path: path.to_string(),
+            page,
+            language: language.map(str::to_string),
+        }
+    }
+}
+
+impl Display for AssetContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}`", self.path)?;
+        if let Some(page) = self.page {
+            write!(f, " page: {page}")?;
+        }
+        if let Some(language) = &self.language {
+            write!(f, " language: `{language}`")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FlipbookError {
+    #[error("asset not found: {context}")]
+    AssetMissing { context: AssetContext },
+
+    #[error("unsupported asset format: {context}")]
+    UnsupportedFormat { context: AssetContext },
+
+    #[error("asset content is {content} but its extension says otherwise: {context}")]
+    FormatMismatch {
+        context: AssetContext,
+        content: BinaryFormat,
+    },
+
+    #[error("invalid image variant `{name}`: {reason}")]
+    InvalidImageVariant { name: String, reason: String },
+
+    #[error("invalid text block {block} of page {page}: {reason}")]
+    InvalidTextBlock {
+        page: usize,
+        block: usize,
+        reason: String,
+    },
+
+    #[error("invalid language")]
+    InvalidLanguage {
+        #[source]
+        source: LanguageTagError,
+    },
+
+    #[error("inconsistent languages: {reason}")]
+    InconsistentLanguages { reason: String },
+
+    #[error("invalid markup in text `{string_id}`")]
+    InvalidMarkup {
+        string_id: StringID,
+        #[source]
+        source: MarkupError,
+    },
+
+    #[error("no miniature: the source has none and no page to make it from")]
+    NoMiniature,
+
+    #[error("error processing image: {context}")]
+    ImageProcessing {
+        context: AssetContext,
+        /// Boxed, it's much larger than every other error
+        #[source]
+        source: Box<image::ImageError>,
+    },
+
+    #[error("error processing audio: {context}")]
+    AudioProcessing {
+        context: AssetContext,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// Only WAV can be written, see `AudioOptions::encoding`
+    #[error("can't keep {format} after trimming or normalizing, choose WAV: {context}")]
+    AudioEncodingUnavailable {
+        context: AssetContext,
+        format: BinaryFormat,
+    },
+
+    #[error("invalid timing track: {context}")]
+    InvalidTiming {
+        context: AssetContext,
+        #[source]
+        source: TimingParseError,
+    },
+
+    #[error("timing track ends at {end_ms} ms, after its audio: {duration_ms} ms: {context}")]
+    TimingOutOfRange {
+        context: AssetContext,
+        end_ms: u64,
+        duration_ms: u64,
+    },
+
+    #[error("error reading asset: {context}")]
+    AssetRead {
+        context: AssetContext,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("error reading source `{path}`")]
+    SourceRead {
+        path: FilePath,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("invalid source JSON `{path}`")]
+    InvalidSource {
+        path: FilePath,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("error reading package `{path}`")]
+    PackageRead {
+        path: FilePath,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error(
+        "asset out of range in `{path}`: {start}+{length}, package length is {package_length}"
+    )]
+    AssetOutOfRange {
+        path: FilePath,
+        start: u64,
+        length: u64,
+        package_length: u64,
+    },
+
+    #[error("invalid container `{path}`: {reason}")]
+    InvalidContainer { path: FilePath, reason: String },
+
+    #[error("invalid package metadata `{path}`")]
+    InvalidPackage {
+        path: FilePath,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("invalid miniature in `{path}`")]
+    InvalidMiniature {
+        path: FilePath,
+        #[source]
+        source: base64::DecodeError,
+    },
+
+    #[error("package `{path}` has an unknown version: {version}")]
+    UnsupportedPackageVersion {
+        path: FilePath,
+        version: MetadataVersion,
+    },
+
+    #[error("invalid output path `{path}`")]
+    InvalidOutputPath { path: FilePath },
+
+    #[error("error writing output `{path}`")]
+    OutputWrite {
+        path: FilePath,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("error serializing metadata into `{path}`")]
+    MetadataSerialization {
+        path: FilePath,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl FlipbookError {
+    /// Reading an asset failing because it isn't there is common enough to deserve its own variant
+    pub fn from_asset_read(context: AssetContext, source: std::io::Error) -> Self {
+        if source.kind() == std::io::ErrorKind::NotFound {
+            Self::AssetMissing { context }
+        } else {
+            Self::AssetRead { context, source }
+        }
+    }
+}
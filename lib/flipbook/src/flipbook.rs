@@ -1,3 +1,8 @@
 pub mod common;
+pub mod format;
+pub mod language;
+pub mod layout;
+pub mod markup;
 pub mod package;
 pub mod source;
+pub mod timing;
@@ -5,7 +5,7 @@ pub type MetadataVersion = u16;
 /// This is a "true string" something the reader will see
 pub type RawString = String;
 
-/// As stupidly simple as possible, even an enum, perhaps?
+/// A canonical BCP 47 tag: `en`, `sv`, `es-MX`. Sources may write others, see `language`
 pub type LanguageCode = String;
 
 pub type FilePath = String;
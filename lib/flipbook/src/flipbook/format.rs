@@ -0,0 +1,251 @@
+// Formats of the assets packed into a flipbook. The content of a file decides its format, not its
+//   name: a PNG called `.jpg` is still a PNG for whoever decodes it.
+use std::fmt::Display;
+use std::io::Read;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Bytes read from the start of a file to tell its format
+pub const SNIFF_LENGTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Audio,
+}
+
+/// Serialized as the usual extension of the format, what the client expects in
+///   `FilePositionInPackage::format`. Any extension `from_extension` knows is read back, in any
+///   case: older packages carry `JPG` or `jpeg`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryFormat {
+    Jpg,
+    Png,
+    Webp,
+    Gif,
+    Mp3,
+    Ogg,
+    Wav,
+    Flac,
+    /// Only as an input of the audio stage, see `CompileOptions::audio`
+    Aiff,
+}
+
+/// Every extension a format is known by, the usual one first
+const EXTENSIONS: &[(&str, BinaryFormat)] = &[
+    ("jpg", BinaryFormat::Jpg),
+    ("jpeg", BinaryFormat::Jpg),
+    ("png", BinaryFormat::Png),
+    ("webp", BinaryFormat::Webp),
+    ("gif", BinaryFormat::Gif),
+    ("mp3", BinaryFormat::Mp3),
+    ("ogg", BinaryFormat::Ogg),
+    ("oga", BinaryFormat::Ogg),
+    ("wav", BinaryFormat::Wav),
+    ("flac", BinaryFormat::Flac),
+    ("aif", BinaryFormat::Aiff),
+    ("aiff", BinaryFormat::Aiff),
+    ("aifc", BinaryFormat::Aiff),
+];
+
+impl BinaryFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinaryFormat::Jpg => "jpg",
+            BinaryFormat::Png => "png",
+            BinaryFormat::Webp => "webp",
+            BinaryFormat::Gif => "gif",
+            BinaryFormat::Mp3 => "mp3",
+            BinaryFormat::Ogg => "ogg",
+            BinaryFormat::Wav => "wav",
+            BinaryFormat::Flac => "flac",
+            BinaryFormat::Aiff => "aiff",
+        }
+    }
+
+    pub fn kind(self) -> MediaKind {
+        match self {
+            BinaryFormat::Jpg | BinaryFormat::Png | BinaryFormat::Webp | BinaryFormat::Gif => {
+                MediaKind::Image
+            }
+            BinaryFormat::Mp3
+            | BinaryFormat::Ogg
+            | BinaryFormat::Wav
+            | BinaryFormat::Flac
+            | BinaryFormat::Aiff => MediaKind::Audio,
+        }
+    }
+
+    /// The format a file claims to have through its extension, case insensitive
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        EXTENSIONS
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+            .map(|&(_, format)| format)
+    }
+
+    /// The extensions, in lowercase, of the formats of a kind
+    pub fn extensions(kind: MediaKind) -> impl Iterator<Item = &'static str> {
+        EXTENSIONS
+            .iter()
+            .filter(move |(_, format)| format.kind() == kind)
+            .map(|&(extension, _)| extension)
+    }
+
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?;
+        Self::from_extension(extension)
+    }
+
+    /// The format of some content, told by its magic bytes. `SNIFF_LENGTH` bytes are enough
+    pub fn sniff(content: &[u8]) -> Option<Self> {
+        let riff_type =
+            |kind: &[u8]| content.starts_with(b"RIFF") && content.get(8..12) == Some(kind);
+        let form_type =
+            |kind: &[u8]| content.starts_with(b"FORM") && content.get(8..12) == Some(kind);
+        if content.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(BinaryFormat::Jpg)
+        } else if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(BinaryFormat::Png)
+        } else if content.starts_with(b"GIF87a") || content.starts_with(b"GIF89a") {
+            Some(BinaryFormat::Gif)
+        } else if riff_type(b"WEBP") {
+            Some(BinaryFormat::Webp)
+        } else if riff_type(b"WAVE") {
+            Some(BinaryFormat::Wav)
+        } else if form_type(b"AIFF") || form_type(b"AIFC") {
+            Some(BinaryFormat::Aiff)
+        } else if content.starts_with(b"OggS") {
+            Some(BinaryFormat::Ogg)
+        } else if content.starts_with(b"fLaC") {
+            Some(BinaryFormat::Flac)
+        } else if content.starts_with(b"ID3")
+            || matches!(content, [0xff, second, ..] if second & 0xe0 == 0xe0)
+        {
+            Some(BinaryFormat::Mp3)
+        } else {
+            None
+        }
+    }
+
+    /// Sniffs the start of a file
+    pub fn sniff_file(path: &str) -> std::io::Result<Option<Self>> {
+        let mut head = Vec::with_capacity(SNIFF_LENGTH);
+        std::fs::File::open(path)?
+            .take(SNIFF_LENGTH as u64)
+            .read_to_end(&mut head)?;
+        Ok(Self::sniff(&head))
+    }
+}
+
+impl Serialize for BinaryFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for BinaryFormat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let extension = String::deserialize(deserializer)?;
+        Self::from_extension(&extension)
+            .ok_or_else(|| de::Error::custom(format!("unknown binary format `{extension}`")))
+    }
+}
+
+impl Display for BinaryFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_decides_the_format() {
+        assert_eq!(
+            BinaryFormat::sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some(BinaryFormat::Png)
+        );
+        assert_eq!(
+            BinaryFormat::sniff(&[0xff, 0xd8, 0xff, 0xe0]),
+            Some(BinaryFormat::Jpg)
+        );
+        assert_eq!(
+            BinaryFormat::sniff(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(BinaryFormat::Webp)
+        );
+        assert_eq!(
+            BinaryFormat::sniff(b"RIFF\0\0\0\0WAVEfmt "),
+            Some(BinaryFormat::Wav)
+        );
+        assert_eq!(
+            BinaryFormat::sniff(&[0xff, 0xfb, 0x90]),
+            Some(BinaryFormat::Mp3)
+        );
+        assert_eq!(BinaryFormat::sniff(b"image 00"), None);
+
+        assert_eq!(BinaryFormat::from_path("a/b.JPEG"), Some(BinaryFormat::Jpg));
+        assert_eq!(BinaryFormat::from_path("a/b"), None);
+
+        // Older packages may carry `jpeg` or upper case, they're read as the usual extension
+        for (legacy, usual) in [
+            ("jpeg", "jpg"),
+            ("JPG", "jpg"),
+            ("PNG", "png"),
+            ("MP3", "mp3"),
+        ] {
+            let format: BinaryFormat = serde_json::from_str(&format!("\"{legacy}\"")).unwrap();
+            assert_eq!(
+                serde_json::to_string(&format).unwrap(),
+                format!("\"{usual}\"")
+            );
+        }
+        assert!(serde_json::from_str::<BinaryFormat>("\"bmp\"").is_err());
+
+        let audio: Vec<_> = BinaryFormat::extensions(MediaKind::Audio).collect();
+        assert!(audio.contains(&"flac") && audio.contains(&"aiff"));
+        assert!(BinaryFormat::extensions(MediaKind::Image).any(|e| e == "gif"));
+    }
+
+    #[test]
+    fn compiles_what_the_content_says() {
+        use crate::compile::CompileOptions;
+        use crate::error::FlipbookError;
+        use crate::test_utils::{compile_source, sample_source, scratch_dir};
+
+        let dir = scratch_dir("formats");
+        let png = b"\x89PNG\r\n\x1a\npretend png";
+        std::fs::write(dir.join("01.jpg"), png).unwrap();
+        let source = sample_source(&dir);
+
+        let reader = compile_source(&dir, &source, &CompileOptions::default()).unwrap();
+        let package = reader.package();
+        let image = package.pages[1].image.as_ref().unwrap();
+        assert_eq!(image.format, BinaryFormat::Png);
+        // Nothing tells what `image 00` is, the extension is trusted
+        assert_eq!(
+            package.pages[0].image.as_ref().unwrap().format,
+            BinaryFormat::Jpg
+        );
+
+        let strict = CompileOptions {
+            strict_formats: true,
+            ..Default::default()
+        };
+        std::fs::write(dir.join("00.jpg"), [0xff, 0xd8, 0xff, 0xe0]).unwrap();
+        assert!(matches!(
+            compile_source(&dir, &source, &strict),
+            Err(FlipbookError::FormatMismatch { context, content: BinaryFormat::Png })
+                if context.page == Some(1)
+        ));
+
+        // An image can't be an audio, strict or not
+        std::fs::write(dir.join("02_en.ogg"), png).unwrap();
+        assert!(matches!(
+            compile_source(&dir, &source, &CompileOptions::default()),
+            Err(FlipbookError::UnsupportedFormat { context }) if context.page == Some(2)
+        ));
+    }
+}
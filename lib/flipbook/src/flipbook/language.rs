@@ -0,0 +1,343 @@
+// Languages are written as BCP 47 tags: `en`, `sv`, `es-MX`, `sr-Latn`. The tags are what the
+//   packages are keyed with, so books compiled by different tools can be read by the same client.
+// Older sources, and `mock-flipbook` for a while, wrote names like `English` instead: the lenient
+//   parser still understands them, along with `en_US` and ISO 639-2 codes like `swe`, and turns
+//   them into the tag. The language of the tag has to be one of the languages below, a well
+//   formed tag in a language nobody reads yet is more likely a typo than a new book.
+// `se` is refused: the first books used it for Swedish, and as a tag it means Northern Sami.
+use std::fmt::Display;
+
+use super::common::LanguageCode;
+
+/// Languages the names in `KnownLanguage::names` are written in, in that order
+const NAME_LANGUAGES: [&str; 3] = ["en", "sv", "es"];
+
+/// Swedish in the first books, Northern Sami for BCP 47
+const AMBIGUOUS_CODE: &str = "se";
+
+struct KnownLanguage {
+    code: &'static str,
+    /// ISO 639-2 codes, only accepted by `parse_lenient`
+    alpha3: &'static [&'static str],
+    /// In every language of `NAME_LANGUAGES`
+    names: [&'static str; 3],
+    /// In the language itself
+    native: &'static str,
+}
+
+const fn known(
+    code: &'static str,
+    alpha3: &'static [&'static str],
+    names: [&'static str; 3],
+    native: &'static str,
+) -> KnownLanguage {
+    KnownLanguage {
+        code,
+        alpha3,
+        names,
+        native,
+    }
+}
+
+#[rustfmt::skip]
+/// Northern Sami is `sme`, never `se`, see `AMBIGUOUS_CODE`
+const KNOWN_LANGUAGES: &[KnownLanguage] = &[
+    known("ar", &["ara"], ["Arabic", "arabiska", "árabe"], "العربية"),
+    known("bs", &["bos"], ["Bosnian", "bosniska", "bosnio"], "bosanski"),
+    known("ca", &["cat"], ["Catalan", "katalanska", "catalán"], "català"),
+    known("da", &["dan"], ["Danish", "danska", "danés"], "dansk"),
+    known("de", &["deu", "ger"], ["German", "tyska", "alemán"], "Deutsch"),
+    known("en", &["eng"], ["English", "engelska", "inglés"], "English"),
+    known("es", &["spa"], ["Spanish", "spanska", "español"], "español"),
+    known("et", &["est"], ["Estonian", "estniska", "estonio"], "eesti"),
+    known("eu", &["eus", "baq"], ["Basque", "baskiska", "euskera"], "euskara"),
+    known("fa", &["fas", "per"], ["Persian", "persiska", "persa"], "فارسی"),
+    known("fi", &["fin"], ["Finnish", "finska", "finés"], "suomi"),
+    known("fit", &[], ["Meänkieli", "meänkieli", "meänkieli"], "meänkieli"),
+    known("fr", &["fra", "fre"], ["French", "franska", "francés"], "français"),
+    known("gl", &["glg"], ["Galician", "galiciska", "gallego"], "galego"),
+    known("hr", &["hrv"], ["Croatian", "kroatiska", "croata"], "hrvatski"),
+    known("is", &["isl", "ice"], ["Icelandic", "isländska", "islandés"], "íslenska"),
+    known("it", &["ita"], ["Italian", "italienska", "italiano"], "italiano"),
+    known("ja", &["jpn"], ["Japanese", "japanska", "japonés"], "日本語"),
+    known("ku", &["kur"], ["Kurdish", "kurdiska", "kurdo"], "kurdî"),
+    known("nb", &["nob"], ["Norwegian Bokmål", "norskt bokmål", "noruego bokmål"], "norsk bokmål"),
+    known("nl", &["nld", "dut"], ["Dutch", "nederländska", "neerlandés"], "Nederlands"),
+    known("nn", &["nno"], ["Norwegian Nynorsk", "nynorska", "noruego nynorsk"], "nynorsk"),
+    known("no", &["nor"], ["Norwegian", "norska", "noruego"], "norsk"),
+    known("pl", &["pol"], ["Polish", "polska", "polaco"], "polski"),
+    known("pt", &["por"], ["Portuguese", "portugisiska", "portugués"], "português"),
+    known("rom", &[], ["Romani", "romani", "romaní"], "romani čhib"),
+    known("ru", &["rus"], ["Russian", "ryska", "ruso"], "русский"),
+    known("sma", &[], ["Southern Sami", "sydsamiska", "sami meridional"], "åarjelsaemien gïele"),
+    known("sme", &[], ["Northern Sami", "nordsamiska", "sami septentrional"], "davvisámegiella"),
+    known("smj", &[], ["Lule Sami", "lulesamiska", "sami de Lule"], "julevsámegiella"),
+    known("so", &["som"], ["Somali", "somaliska", "somalí"], "Soomaali"),
+    known("sr", &["srp"], ["Serbian", "serbiska", "serbio"], "српски"),
+    known("sv", &["swe"], ["Swedish", "svenska", "sueco"], "svenska"),
+    known("ti", &["tir"], ["Tigrinya", "tigrinska", "tigriña"], "ትግርኛ"),
+    known("tr", &["tur"], ["Turkish", "turkiska", "turco"], "Türkçe"),
+    known("uk", &["ukr"], ["Ukrainian", "ukrainska", "ucraniano"], "українська"),
+    known("yi", &["yid"], ["Yiddish", "jiddisch", "ídish"], "ייִדיש"),
+    known("zh", &["zho", "chi"], ["Chinese", "kinesiska", "chino"], "中文"),
+];
+
+/// Why a language can't be used, `tag` as it was written
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LanguageTagError {
+    pub tag: String,
+    pub reason: String,
+}
+
+impl Display for LanguageTagError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}`: {}", self.tag, self.reason)
+    }
+}
+
+impl std::error::Error for LanguageTagError {}
+
+type TagResult<T> = std::result::Result<T, LanguageTagError>;
+
+/// A BCP 47 tag, with its subtags in their canonical case: `sr-Latn-RS`. The language is an
+///   ISO 639 code, extensions and private use subtags aren't supported
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LanguageTag {
+    pub language: String,
+    /// Four letters, titlecase: `Latn`
+    pub script: Option<String>,
+    /// Two letters, uppercase, or three digits: `MX`, `419`
+    pub region: Option<String>,
+    pub variants: Vec<String>,
+}
+
+impl Display for LanguageTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.language)?;
+        for subtag in self.script.iter().chain(&self.region).chain(&self.variants) {
+            write!(f, "-{subtag}")?;
+        }
+        Ok(())
+    }
+}
+
+fn tag_error(tag: &str, reason: impl Into<String>) -> LanguageTagError {
+    LanguageTagError {
+        tag: tag.to_string(),
+        reason: reason.into(),
+    }
+}
+
+fn known_language(code: &str) -> Option<&'static KnownLanguage> {
+    KNOWN_LANGUAGES.iter().find(|l| l.code == code)
+}
+
+impl LanguageTag {
+    /// A well formed tag, in any case: `en`, `EN-gb`, `sr-latn`. The language doesn't need to be
+    ///   one of `KNOWN_LANGUAGES`
+    pub fn parse(tag: &str) -> TagResult<Self> {
+        let mut subtags = tag.split('-').map(str::to_ascii_lowercase).peekable();
+        let alpha = |s: &str| s.chars().all(|c| c.is_ascii_alphabetic());
+        let digit = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+
+        let language = subtags.next().unwrap_or_default();
+        if !(2..=3).contains(&language.len()) || !alpha(&language) {
+            return Err(tag_error(tag, "the language is two or three letters"));
+        }
+
+        let script = subtags
+            .next_if(|s| s.len() == 4 && alpha(s))
+            .map(|s| s[..1].to_ascii_uppercase() + &s[1..]);
+        let region = subtags
+            .next_if(|s| (s.len() == 2 && alpha(s)) || (s.len() == 3 && digit(s)))
+            .map(|s| s.to_ascii_uppercase());
+        let mut variants = vec![];
+        for subtag in subtags {
+            let alnum = subtag.chars().all(|c| c.is_ascii_alphanumeric());
+            let is_variant = alnum
+                && ((5..=8).contains(&subtag.len())
+                    || (subtag.len() == 4 && subtag.starts_with(|c: char| c.is_ascii_digit())));
+            if !is_variant {
+                return Err(tag_error(tag, format!("unsupported subtag `{subtag}`")));
+            }
+            if variants.contains(&subtag) {
+                return Err(tag_error(tag, format!("repeated variant `{subtag}`")));
+            }
+            variants.push(subtag);
+        }
+        Ok(Self {
+            language,
+            script,
+            region,
+            variants,
+        })
+    }
+
+    /// Anything `parse` takes plus what older sources wrote: names of the languages, in any of
+    ///   `NAME_LANGUAGES` or the language itself (`English`, `svenska`), ISO 639-2 codes (`swe`)
+    ///   and `_` between subtags (`en_US`). The language has to be one of `KNOWN_LANGUAGES`, and
+    ///   `se` is refused, see `AMBIGUOUS_CODE`
+    pub fn parse_lenient(tag: &str) -> TagResult<Self> {
+        let trimmed = tag.trim();
+        let language = trimmed.split(['-', '_']).next().unwrap_or_default();
+        if language.eq_ignore_ascii_case(AMBIGUOUS_CODE) {
+            return Err(tag_error(
+                tag,
+                "ambiguous, older books wrote it for Swedish: write `sv`, or `sme` for Northern Sami",
+            ));
+        }
+        let by_name = KNOWN_LANGUAGES.iter().find(|l| {
+            let lowercase = trimmed.to_lowercase();
+            l.names
+                .iter()
+                .chain([&l.native])
+                .any(|n| n.to_lowercase() == lowercase)
+                || l.alpha3.contains(&lowercase.as_str())
+        });
+        if let Some(known) = by_name {
+            return Self::parse(known.code);
+        }
+        let parsed =
+            Self::parse(&trimmed.replace('_', "-")).map_err(|e| tag_error(tag, e.reason))?;
+        if known_language(&parsed.language).is_none() {
+            return Err(tag_error(
+                tag,
+                format!("unknown language `{}`", parsed.language),
+            ));
+        }
+        Ok(parsed)
+    }
+
+    pub fn code(&self) -> LanguageCode {
+        self.to_string()
+    }
+
+    /// How the language is called in `in_language`, a tag too: `svenska` in `sv`, `Swedish` in
+    ///   `en`. English when there's no name in `in_language`, the tag itself for the languages
+    ///   `parse` takes but `KNOWN_LANGUAGES` doesn't have. The region, if any, goes after: `español (MX)`
+    pub fn display_name(&self, in_language: &str) -> String {
+        let Some(known) = known_language(&self.language) else {
+            return self.code();
+        };
+        let in_language = Self::parse_lenient(in_language)
+            .map(|tag| tag.language)
+            .unwrap_or_default();
+        let name = if in_language == self.language {
+            known.native
+        } else {
+            let position = NAME_LANGUAGES.iter().position(|l| *l == in_language);
+            known.names[position.unwrap_or_default()]
+        };
+        match &self.region {
+            Some(region) => format!("{name} ({region})"),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// `code` as a canonical tag, see `LanguageTag::parse_lenient`
+pub fn canonical(code: &str) -> TagResult<LanguageCode> {
+    LanguageTag::parse_lenient(code).map(|tag| tag.code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_tags_from_legacy_names() {
+        for (written, expected) in [
+            ("en", "en"),
+            ("English", "en"),
+            ("SWEDISH", "sv"),
+            ("svenska", "sv"),
+            ("swe", "sv"),
+            ("es_mx", "es-MX"),
+            ("es-419", "es-419"),
+            ("SR-latn-rs", "sr-Latn-RS"),
+            ("de-CH-1901", "de-CH-1901"),
+            ("sma", "sma"),
+            ("sme", "sme"),
+            ("Northern Sami", "sme"),
+            ("FIT", "fit"),
+        ] {
+            assert_eq!(canonical(written).unwrap(), expected, "{written}");
+        }
+        for invalid in [
+            "",
+            "Klingon",
+            "se",
+            "SE-fi",
+            "e",
+            "en4",
+            "abcd",
+            "en-x-private",
+            "en--US",
+            "en-1901-1901",
+            // Well formed, but not known
+            "ko",
+            "cs-CZ",
+            "ro_MD",
+            "tlh",
+        ] {
+            assert!(canonical(invalid).is_err(), "{invalid}");
+        }
+        assert_eq!(
+            canonical("tlh").unwrap_err().reason,
+            "unknown language `tlh`"
+        );
+        // Only the lenient parser knows about names, and about which languages there are
+        assert!(LanguageTag::parse("English").is_err());
+        assert!(LanguageTag::parse("ko").is_ok());
+
+        let swedish = LanguageTag::parse("sv").unwrap();
+        assert_eq!(swedish.display_name("sv"), "svenska");
+        assert_eq!(swedish.display_name("en"), "Swedish");
+        assert_eq!(swedish.display_name("es-ES"), "sueco");
+        assert_eq!(swedish.display_name("fi"), "Swedish");
+        let mexican = LanguageTag::parse("es-MX").unwrap();
+        assert_eq!(mexican.display_name("es"), "español (MX)");
+        assert_eq!(LanguageTag::parse("ko").unwrap().display_name("en"), "ko");
+    }
+
+    #[test]
+    fn legacy_language_names_compile_as_tags() {
+        use crate::error::FlipbookError;
+        use crate::test_utils::{compile_source, sample_options, sample_source, scratch_dir};
+
+        let dir = scratch_dir("languages");
+        let mut source = sample_source(&dir);
+        source.languages = vec!["English".to_string(), "svenska".to_string()];
+        source.default_language = "ENGLISH".to_string();
+        source
+            .fallbacks
+            .insert("swe".to_string(), vec!["en".to_string()]);
+        let text = source.pages[2].text.as_mut().unwrap();
+        let two = text.0.remove("en").unwrap();
+        text.0.insert("English".to_string(), two);
+
+        let reader = compile_source(&dir, &source, &sample_options()).unwrap();
+        let package = reader.package();
+        assert_eq!(package.languages, vec!["en", "sv"]);
+        assert_eq!(package.default_language, "en");
+        assert_eq!(package.fallbacks["sv"], vec!["en"]);
+        assert_eq!(package.pages[2].texts["en"], "PAGE_2_en");
+        assert_eq!(reader.text(2, "sv").unwrap(), "two");
+
+        let mut unknown = source.clone();
+        unknown.languages.push("Klingon".to_string());
+        assert!(matches!(
+            compile_source(&dir, &unknown, &sample_options()),
+            Err(FlipbookError::InvalidLanguage { .. })
+        ));
+        let mut twice = source.clone();
+        twice
+            .title
+            .0
+            .insert("English".to_string(), Default::default());
+        assert!(matches!(
+            compile_source(&dir, &twice, &sample_options()),
+            Err(FlipbookError::InconsistentLanguages { .. })
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+// Where the text goes on a page. Picture books don't have a text area: the illustrator leaves
+//   room for two or three boxes wherever the picture allows, so the source says where each one
+//   is and the client draws the text there.
+// Everything is relative to the page, the same book looks the same on every screen.
+use serde::{Deserialize, Serialize};
+
+/// A rectangle of the page, in fractions of its width and height: (0, 0) is the top left
+///   corner, (1, 1) the bottom right one
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Slack for the end of a rectangle: a block reaching the edge at `0.27 + 0.66 + 0.07` ends a
+///   hair over 1.0 as `f32`
+const EPSILON: f32 = 1e-4;
+
+impl Rect {
+    /// Not empty and inside the page
+    pub fn is_normalised(&self) -> bool {
+        let inside = |start: f32, length: f32| {
+            (0.0..=1.0).contains(&start) && length > 0.0 && start + length <= 1.0 + EPSILON
+        };
+        inside(self.x, self.width) && inside(self.y, self.height)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+/// Why a block can't be drawn, `None` when it can
+pub fn check_block(rect: &Rect, font_size: Option<f32>) -> Option<&'static str> {
+    if !rect.is_normalised() {
+        return Some("the rectangle has to be inside the page and not empty");
+    }
+    if font_size.is_some_and(|size| !(size > 0.0 && size <= 1.0)) {
+        return Some("the font size is a fraction of the page height");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rectangles_up_to_the_edge() {
+        let rect = |x, width| Rect {
+            x,
+            y: 0.0,
+            width,
+            height: 1.0,
+        };
+        assert!(rect(0.7, 0.3).is_normalised());
+        assert!(rect(0.27 + 0.66, 0.07).is_normalised());
+        assert!(rect(0.1, 0.9).is_normalised());
+        assert!(!rect(0.7, 0.31).is_normalised());
+        assert!(!rect(0.5, 0.0).is_normalised());
+    }
+
+    #[test]
+    fn text_blocks_with_their_own_string_ids() {
+        use crate::compile::decompile;
+        use crate::error::FlipbookError;
+        use crate::test_utils::{compile_source, sample_options, sample_source, scratch_dir};
+
+        let dir = scratch_dir("blocks");
+        let mut source = sample_source(&dir);
+        let audio = dir.join("02_en.ogg").to_str().unwrap().to_string();
+        source.pages[2].blocks = serde_json::from_value(serde_json::json!([
+            {
+                "rect": { "x": 0.05, "y": 0.7, "width": 0.4, "height": 0.25 },
+                "text": { "en": { "text": "left", "audio": { "path": audio } } }
+            },
+            {
+                "rect": { "x": 0.55, "y": 0.05, "width": 0.4, "height": 0.2 },
+                "align": "right",
+                "font_size": 0.05,
+                "text": { "en": { "text": "right" } }
+            }
+        ]))
+        .unwrap();
+        let mut reader = compile_source(&dir, &source, &sample_options()).unwrap();
+
+        let blocks = &reader.package().pages[2].blocks;
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].audio["en"], "PAGE_2_BLOCK_0_en");
+        assert_eq!(blocks[1].texts["en"], "PAGE_2_BLOCK_1_en");
+        assert_eq!(blocks[1].align, TextAlign::Right);
+        assert_eq!(reader.block_text(2, 1, "en").unwrap(), "right");
+        assert_eq!(reader.text(2, "en").unwrap(), "two");
+        let block_audio = reader.block_audio(2, 0, "en").unwrap().unwrap();
+        assert_eq!(block_audio.content, b"audio 02");
+
+        let decompiled = decompile(&mut reader, dir.join("decompiled").to_str().unwrap()).unwrap();
+        let page = &decompiled.pages[2];
+        assert_eq!(page.blocks[1].rect, source.pages[2].blocks[1].rect);
+        assert_eq!(page.blocks[1].font_size, Some(0.05));
+        assert_eq!(page.blocks[0].text.0["en"].text, "left");
+        assert!(page.blocks[0].text.0["en"].audio.is_some());
+        assert_eq!(page.text.as_ref().unwrap().0["en"].text, "two");
+
+        source.pages[2].blocks[1].rect.width = 0.6;
+        assert!(matches!(
+            compile_source(&dir, &source, &sample_options()),
+            Err(FlipbookError::InvalidTextBlock {
+                page: 2,
+                block: 1,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,403 @@
+// Markup for the texts of a book. Authors need a bit more than plain text: emphasis, the name of
+//   a character in its colour, a line break exactly where the rhyme asks for it, or how to say an
+//   invented word. The syntax is a handful of BBCode-like tags:
+//   [b]bold[/b], [i]italic[/i], [color=#e08020]coloured[/color], [br] and
+//   [say=ˈmuːmɪn]Mumin[/say]. Raw line breaks count as `[br]`.
+// Anything in brackets that isn't one of those tags is text, so the texts written before there
+//   was markup read the same: `[note]` is just `[note]`. `[[` writes a literal `[` where it
+//   would start a tag, `[[b]` is `[b]`.
+// No other tag is accepted, so there's nothing in a text a client could be tricked into running.
+// Compiled texts keep a plain version of every text for the clients that don't draw markup.
+use std::fmt::Display;
+
+use super::common::RawString;
+
+/// How a piece of text is drawn
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    /// Lowercase `#rrggbb`
+    pub color: Option<String>,
+    /// How to say the text, for voices and reading aids
+    pub say: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Span {
+    Text { text: RawString, style: Style },
+    LineBreak,
+}
+
+/// Why a text can't be parsed, `position` is the byte where the problem starts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MarkupError {
+    pub position: usize,
+    pub reason: String,
+}
+
+impl Display for MarkupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at byte {}: {}", self.position, self.reason)
+    }
+}
+
+impl std::error::Error for MarkupError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tag {
+    Bold,
+    Italic,
+    Color,
+    Say,
+}
+
+impl Tag {
+    fn name(&self) -> &'static str {
+        match self {
+            Tag::Bold => "b",
+            Tag::Italic => "i",
+            Tag::Color => "color",
+            Tag::Say => "say",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "b" => Some(Tag::Bold),
+            "i" => Some(Tag::Italic),
+            "color" => Some(Tag::Color),
+            "say" => Some(Tag::Say),
+            _ => None,
+        }
+    }
+}
+
+fn markup_error(position: usize, reason: impl Into<String>) -> MarkupError {
+    MarkupError {
+        position,
+        reason: reason.into(),
+    }
+}
+
+/// `#rgb` or `#rrggbb`, as lowercase `#rrggbb`
+fn parse_color(value: &str) -> Option<String> {
+    let hex = value.strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect(),
+        6 => hex.to_string(),
+        _ => return None,
+    };
+    Some(format!("#{}", hex.to_lowercase()))
+}
+
+/// Splits `text` in spans, each with the style of the tags around it. Adjacent spans with the
+///   same style are merged
+pub fn parse(text: &str) -> Result<Vec<Span>, MarkupError> {
+    let mut spans = vec![];
+    // With the colour of every `[color=..]`, they nest
+    let mut open: Vec<(Tag, usize, Option<String>)> = vec![];
+    let mut style = Style::default();
+    let mut current = String::new();
+
+    let push_text = |spans: &mut Vec<Span>, current: &mut String, style: &Style| {
+        if current.is_empty() {
+            return;
+        }
+        let text = std::mem::take(current);
+        match spans.last_mut() {
+            Some(Span::Text {
+                text: last,
+                style: s,
+            }) if s == style => last.push_str(&text),
+            _ => spans.push(Span::Text {
+                text,
+                style: style.clone(),
+            }),
+        }
+    };
+
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let position = text.len() - rest.len();
+        match c {
+            '[' if rest.starts_with("[[") => {
+                current.push('[');
+                rest = &rest[2..];
+                continue;
+            }
+            '[' => {
+                let tag = rest.find(']').map(|end| &rest[1..end]).unwrap_or_default();
+                let name = tag.strip_prefix('/').unwrap_or(tag);
+                let name = name.split_once('=').map_or(name, |(name, _)| name);
+                if tag != "br" && Tag::from_name(name).is_none() {
+                    current.push('[');
+                    rest = &rest[1..];
+                    continue;
+                }
+                rest = &rest[tag.len() + 2..];
+                push_text(&mut spans, &mut current, &style);
+
+                if tag == "br" {
+                    spans.push(Span::LineBreak);
+                    continue;
+                }
+                if let Some(name) = tag.strip_prefix('/') {
+                    let closed = Tag::from_name(name)
+                        .ok_or_else(|| markup_error(position, format!("unknown tag `{tag}`")))?;
+                    match open.pop() {
+                        Some((tag, _, _)) if tag == closed => {}
+                        Some((tag, _, _)) => {
+                            return Err(markup_error(
+                                position,
+                                format!("`[/{name}]` closes `[{}]`", tag.name()),
+                            ))
+                        }
+                        None => {
+                            return Err(markup_error(position, format!("`[/{name}]` never opened")))
+                        }
+                    }
+                    match closed {
+                        Tag::Bold => style.bold = false,
+                        Tag::Italic => style.italic = false,
+                        // The colour outside applies again
+                        Tag::Color => {
+                            style.color = open.iter().rev().find_map(|(_, _, color)| color.clone())
+                        }
+                        Tag::Say => style.say = None,
+                    }
+                    continue;
+                }
+
+                let (name, value) = match tag.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (tag, None),
+                };
+                let opened = Tag::from_name(name)
+                    .ok_or_else(|| markup_error(position, format!("unknown tag `{tag}`")))?;
+                let already = |what: &str| markup_error(position, format!("already {what}"));
+                match (opened, value) {
+                    (Tag::Bold, None) if style.bold => return Err(already("bold")),
+                    (Tag::Bold, None) => style.bold = true,
+                    (Tag::Italic, None) if style.italic => return Err(already("italic")),
+                    (Tag::Italic, None) => style.italic = true,
+                    (Tag::Color, Some(value)) => {
+                        style.color = Some(parse_color(value).ok_or_else(|| {
+                            markup_error(position, format!("`{value}` isn't `#rgb` or `#rrggbb`"))
+                        })?)
+                    }
+                    (Tag::Say, Some(_)) if style.say.is_some() => {
+                        return Err(already("with a pronunciation"))
+                    }
+                    (Tag::Say, Some(value)) if !value.trim().is_empty() => {
+                        style.say = Some(value.trim().to_string())
+                    }
+                    _ => return Err(markup_error(position, format!("invalid tag `{tag}`"))),
+                }
+                let color = (opened == Tag::Color)
+                    .then(|| style.color.clone())
+                    .flatten();
+                open.push((opened, position, color));
+            }
+            '\n' => {
+                push_text(&mut spans, &mut current, &style);
+                spans.push(Span::LineBreak);
+                rest = &rest[1..];
+            }
+            '\r' if rest.starts_with("\r\n") => rest = &rest[1..],
+            c => {
+                current.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if let Some((tag, position, _)) = open.last() {
+        return Err(markup_error(
+            *position,
+            format!("`[{}]` never closed", tag.name()),
+        ));
+    }
+    push_text(&mut spans, &mut current, &style);
+    Ok(spans)
+}
+
+/// What a client that doesn't draw markup shows: the text, with its line breaks
+pub fn plain_text(spans: &[Span]) -> RawString {
+    spans
+        .iter()
+        .map(|span| match span {
+            Span::Text { text, .. } => text.as_str(),
+            Span::LineBreak => "\n",
+        })
+        .collect()
+}
+
+/// The same spans always give the same markup: every span with its tags, in a fixed order
+pub fn normalize(spans: &[Span]) -> RawString {
+    let mut markup = String::new();
+    for span in spans {
+        let (text, style) = match span {
+            Span::Text { text, style } => (text, style),
+            Span::LineBreak => {
+                markup.push_str("[br]");
+                continue;
+            }
+        };
+        let mut closing = vec![];
+        if let Some(color) = &style.color {
+            markup.push_str(&format!("[color={color}]"));
+            closing.push("[/color]");
+        }
+        if style.bold {
+            markup.push_str("[b]");
+            closing.push("[/b]");
+        }
+        if style.italic {
+            markup.push_str("[i]");
+            closing.push("[/i]");
+        }
+        if let Some(say) = &style.say {
+            markup.push_str(&format!("[say={say}]"));
+            closing.push("[/say]");
+        }
+        markup.push_str(&text.replace('[', "[["));
+        closing.iter().rev().for_each(|tag| markup.push_str(tag));
+    }
+    markup
+}
+
+/// Both versions of `text`: (plain text, normalised markup). The markup is `None` when it would
+///   say nothing the plain text doesn't, and parsing the plain text gives the same text back:
+///   with a `[` it may be read as a tag
+pub fn compile(text: &str) -> Result<(RawString, Option<RawString>), MarkupError> {
+    let spans = parse(text)?;
+    let plain = plain_text(&spans);
+    let has_markup = plain.contains('[')
+        || spans.iter().any(|span| match span {
+            Span::Text { style, .. } => style != &Style::default(),
+            Span::LineBreak => false,
+        });
+    let markup = has_markup.then(|| normalize(&spans));
+    Ok((plain, markup))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str, style: Style) -> Span {
+        Span::Text {
+            text: text.to_string(),
+            style,
+        }
+    }
+
+    #[test]
+    fn parses_and_normalizes() {
+        let source = "[color=#E82]Mumin[/color] said: [b]look, [i]a [[star][/i][/b]![br]\
+                      [say=ˈmuːmɪn]Mumin[/say]";
+        let spans = parse(source).unwrap();
+        let orange = Style {
+            color: Some("#ee8822".to_string()),
+            ..Default::default()
+        };
+        let bold = Style {
+            bold: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            spans,
+            vec![
+                text("Mumin", orange),
+                text(" said: ", Style::default()),
+                text("look, ", bold.clone()),
+                text(
+                    "a [star]",
+                    Style {
+                        italic: true,
+                        ..bold
+                    }
+                ),
+                text("!", Style::default()),
+                Span::LineBreak,
+                text(
+                    "Mumin",
+                    Style {
+                        say: Some("ˈmuːmɪn".to_string()),
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+        assert_eq!(plain_text(&spans), "Mumin said: look, a [star]!\nMumin");
+        let normalized = normalize(&spans);
+        assert_eq!(parse(&normalized).unwrap(), spans);
+        assert_eq!(normalize(&parse(&normalized).unwrap()), normalized);
+
+        assert_eq!(
+            compile("two\nlines").unwrap(),
+            ("two\nlines".to_string(), None)
+        );
+        // Brackets that aren't tags are text, a `[` the markup keeps escaped
+        let literal = "see [note], [u]x[/u] [br=2] [[b] [y";
+        assert_eq!(
+            compile(literal).unwrap(),
+            (
+                "see [note], [u]x[/u] [br=2] [b] [y".to_string(),
+                Some("see [[note], [[u]x[[/u] [[br=2] [[b] [[y".to_string())
+            )
+        );
+        assert_eq!(
+            parse("[b]open").unwrap_err(),
+            markup_error(0, "`[b]` never closed")
+        );
+        for invalid in [
+            "[b]x[/i]",
+            "[/b]",
+            "[b]x",
+            "[color=red]x[/color]",
+            "[say=]x[/say]",
+        ] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn markup_with_a_plain_fallback() {
+        use crate::compile::decompile;
+        use crate::error::FlipbookError;
+        use crate::flipbook::source::FlipbookSource;
+        use crate::test_utils::{compile_source, sample_options, sample_source, scratch_dir};
+
+        let dir = scratch_dir("markup");
+        let mut source = sample_source(&dir);
+        fn text(source: &mut FlipbookSource) -> &mut String {
+            let page = source.pages[2].text.as_mut().unwrap();
+            &mut page.0.get_mut("en").unwrap().text
+        }
+        *text(&mut source) = "[b]two".to_string();
+        assert!(matches!(
+            compile_source(&dir, &source, &sample_options()),
+            Err(FlipbookError::InvalidMarkup { string_id, .. }) if string_id == "PAGE_2_en"
+        ));
+
+        *text(&mut source) = "[color=#E82][b]two[/b][/color]\npages".to_string();
+        let mut reader = compile_source(&dir, &source, &sample_options()).unwrap();
+        let package = reader.package();
+        assert_eq!(package.texts["PAGE_2_en"], "two\npages");
+        assert_eq!(
+            package.markup["PAGE_2_en"],
+            "[color=#ee8822][b]two[/b][/color][br]pages"
+        );
+        assert!(!package.markup.contains_key("PAGE_1_en"));
+        assert_eq!(reader.markup("PAGE_1_en").unwrap(), "one");
+        let markup = package.markup["PAGE_2_en"].clone();
+
+        let decompiled = decompile(&mut reader, dir.join("decompiled").to_str().unwrap()).unwrap();
+        let page = decompiled.pages[2].text.as_ref().unwrap();
+        assert_eq!(page.0["en"].text, markup);
+    }
+}
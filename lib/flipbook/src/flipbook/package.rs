@@ -8,58 +8,145 @@
 
 // Layout of the binary package
 // [ Cover, Image page 00, Image page 01, .. Image page N]
-// Optional, per page: [Audio page 01, .., Audio page N]
+// Optional, per page: [Audio page 01, .., Audio page N], the audio of a page ordered by language
+// Optional, per variant: [Image page 00, .., Image page N], unless the variant has its own package
+// Maps are ordered too, so compiling the same source twice yields byte-identical artifacts
+
+// Versions of the metadata
+//   1. The original layout: the pages are only known through `images_in_pages`
+//   2. An explicit record per page and digests of the assets
+// A version only grows additive fields, with `#[serde(default)]` and skipped when empty, so
+//   anything written earlier in its life still reads. Removing or reshaping a field bumps it.
+// Everything in the library works with the newest version, `load` and `from_json` upgrade the
+//   older ones in memory while `migrate` rewrites them on disk.
 
 // Taking some ideas from logSeq: #lillaOrd-flipbook-compiler
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
 use super::common::{LanguageCode, MetadataVersion, RawString};
+use super::format::BinaryFormat;
+use super::layout::{Rect, TextAlign};
+use super::timing::TimingTrack;
+
+mod load;
+mod v1;
+mod v2;
+
+pub use load::{from_json, load, migrate, MigrationOutcome};
+pub use v1::FlipbookPackageV1;
+pub use v2::FlipbookPackageV2;
+
+/// The version every compiled flipbook is emitted with
+pub const CURRENT_VERSION: MetadataVersion = 2;
+
+/// The newest version of the metadata, the one the library works with
+pub type FlipbookPackage = FlipbookPackageV2;
 
 type Base64Image = String;
 type BinaryPackageURL = String;
-/// For the client could be useful to know if the origin is a PNG or a JPEG or a WAV or an OGG
-type BinaryFormat = String;
+
+/// Name of a resolution variant of the backgrounds: `720p`, `1080p`, ..
+pub type VariantName = String;
+
+/// Lowercase hex SHA-256 of the bytes it refers to
+pub type Digest = String;
 
 /// To be honest here I'm thinking about a convention such as:
 ///   ID_00_en -> A line "ID_00" in English
 ///   ID_00_es -> A line "ID_00" in Spanish
-///   ID_00_se -> A LINE "ID_00" in Swedish
+///   ID_00_sv -> A line "ID_00" in Swedish
 pub type StringID = String;
 
 #[allow(non_camel_case_types)]
-pub type TextDB = HashMap<StringID, RawString>;
+pub type TextDB = BTreeMap<StringID, RawString>;
 
 #[allow(non_camel_case_types)]
-pub type AudioDB = HashMap<StringID, FilePositionInPackage>;
+pub type AudioDB = BTreeMap<StringID, FilePositionInPackage>;
+
+/// Under the StringID of the text, and audio, they belong to
+pub type TimingDB = BTreeMap<StringID, Vec<TimingTrack>>;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct FilePositionInPackage {
+    /// For the client could be useful to know if the origin is a PNG or a JPEG or a WAV or an OGG
     pub format: BinaryFormat,
     pub start: u64,
     pub length: u64,
+    /// Digest of the `length` bytes from `start`, so the client can check what it sliced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<Digest>,
+    /// Of images processed while compiling, see `CompileOptions::images`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<Dimensions>,
+    /// Of audio processed while compiling, see `CompileOptions::audio`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_properties: Option<AudioProperties>,
+}
+
+/// What a player needs to know of an audio before decoding it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AudioProperties {
+    pub duration_ms: u64,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Size of an image, in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Everything that belongs to a page, its position in `FlipbookPackage::pages` is the page number
+///   so a missing asset never shifts the rest of the book. Introduced by version 2
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct PageInPackage {
+    /// `None` when the page was compiled without a background image
+    pub image: Option<FilePositionInPackage>,
+    /// Language -> key in `FlipbookPackage::texts`
+    pub texts: BTreeMap<LanguageCode, StringID>,
+    /// Language -> key in `FlipbookPackage::audio`
+    pub audio: BTreeMap<LanguageCode, StringID>,
+    /// The background in other resolutions. They're in the binary package, unless the variant has
+    ///   an entry in `FlipbookPackage::variant_packages`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variants: BTreeMap<VariantName, FilePositionInPackage>,
+    /// Texts to be drawn at a fixed place of the page, in the order of the source
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<BlockInPackage>,
 }
 
-/// This is the "high level item" that the client will use to allow the user select which flipbook is going to be "played"
+/// A text block of a page, its StringIDs are `PAGE_<page no.>_BLOCK_<block no.>_<lang>`
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct FlipbookPackage {
-    /// Version of this data definition (it's possible to predict the format that's incoming)
-    pub version: MetadataVersion,
-    pub languages: Vec<LanguageCode>,
-    pub default_language: LanguageCode,
-    // ---- flipbook resources link
-    pub binary_package_url: BinaryPackageURL,
-    /// See note for StringID but I'm suggesting something like: ID_00_en
-    pub texts: TextDB,
-    pub audio: AudioDB,
-
-    // ---- This is more "natural data" of the object
-    pub title: StringID,
-    pub summary: StringID,
-    /// This is an embedded image in the payload of the Package metadata. The idea is that when the
-    pub miniature: Base64Image,
-
-    /// Images on the pages: they're expected to exist
-    pub images_in_pages: Vec<FilePositionInPackage>,
+pub struct BlockInPackage {
+    pub rect: Rect,
+    pub align: TextAlign,
+    /// Fraction of the page height, `None` leaves it to the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f32>,
+    /// Language -> key in `FlipbookPackage::texts`
+    pub texts: BTreeMap<LanguageCode, StringID>,
+    /// Language -> key in `FlipbookPackage::audio`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub audio: BTreeMap<LanguageCode, StringID>,
+}
+
+/// A binary package with the images of a single variant, so a client only downloads the
+///   resolution it draws
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VariantPackage {
+    pub url: BinaryPackageURL,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<Digest>,
+}
+
+impl VariantPackage {
+    /// It's written next to the main binary package, the one under `path_binary`
+    pub fn path_next_to(&self, path_binary: &Path) -> PathBuf {
+        path_binary.with_file_name(&self.url)
+    }
 }
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{FlipbookError, Result};
+use crate::flipbook::common::MetadataVersion;
+use crate::verify::hash_range;
+
+use super::{
+    Digest, FilePositionInPackage, FlipbookPackage, FlipbookPackageV1, VariantName, CURRENT_VERSION,
+};
+
+/// Every version of the metadata has, at least, this
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: MetadataVersion,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationOutcome {
+    AlreadyCurrent,
+    Migrated { from: MetadataVersion },
+}
+
+fn invalid_package(path: &str) -> impl FnOnce(serde_json::Error) -> FlipbookError + '_ {
+    move |source| FlipbookError::InvalidPackage {
+        path: path.to_string(),
+        source,
+    }
+}
+
+fn read_error(path: &str) -> impl Fn(std::io::Error) -> FlipbookError + '_ {
+    move |source| FlipbookError::PackageRead {
+        path: path.to_string(),
+        source,
+    }
+}
+
+fn probe_version(content: &str, path: &str) -> Result<MetadataVersion> {
+    let probe: VersionProbe = serde_json::from_str(content).map_err(invalid_package(path))?;
+    Ok(probe.version)
+}
+
+/// Deserializes metadata of any known version, upgrading it to the newest one. `path` is where
+///   `content` comes from, it only gives context to the errors
+pub fn from_json(content: &str, path: &str) -> Result<FlipbookPackage> {
+    match probe_version(content, path)? {
+        1 => {
+            let v1: FlipbookPackageV1 =
+                serde_json::from_str(content).map_err(invalid_package(path))?;
+            Ok(v1.into())
+        }
+        CURRENT_VERSION => serde_json::from_str(content).map_err(invalid_package(path)),
+        version => Err(FlipbookError::UnsupportedPackageVersion {
+            path: path.to_string(),
+            version,
+        }),
+    }
+}
+
+/// Reads the metadata under `path`, whatever its version, as the newest one
+pub fn load(path: &str) -> Result<FlipbookPackage> {
+    let content = std::fs::read_to_string(path).map_err(read_error(path))?;
+    from_json(&content, path)
+}
+
+/// Rewrites the metadata under `path_metadata` with the newest version. The layout of the binary
+///   package hasn't changed between versions so it's left untouched, but when it's found next to
+///   the metadata it's read to fill the digests older versions lack
+pub fn migrate(path_metadata: &str) -> Result<MigrationOutcome> {
+    let content = std::fs::read_to_string(path_metadata).map_err(read_error(path_metadata))?;
+    let from = probe_version(&content, path_metadata)?;
+    if from == CURRENT_VERSION {
+        return Ok(MigrationOutcome::AlreadyCurrent);
+    }
+
+    let mut package = from_json(&content, path_metadata)?;
+
+    let path_binary = Path::new(path_metadata)
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(&package.binary_package_url);
+    if path_binary.is_file() {
+        fill_digests(&mut package, path_binary.to_str().unwrap_or_default())?;
+    } else {
+        tracing::warn!(
+            "Binary package {:#?} not found, `{}` migrated without digests",
+            path_binary,
+            path_metadata
+        );
+    }
+
+    // Written aside first, a failure half way never leaves a broken metadata behind
+    let path_temporary = format!("{path_metadata}.migrating");
+    let output_error = |source| FlipbookError::OutputWrite {
+        path: path_metadata.to_string(),
+        source,
+    };
+    {
+        let f = File::create(&path_temporary).map_err(output_error)?;
+        serde_json::to_writer_pretty(f, &package).map_err(|source| {
+            FlipbookError::MetadataSerialization {
+                path: path_metadata.to_string(),
+                source,
+            }
+        })?;
+    }
+    std::fs::rename(&path_temporary, path_metadata).map_err(output_error)?;
+
+    Ok(MigrationOutcome::Migrated { from })
+}
+
+/// Fills the digests missing in `package` from the binary package under `path_binary`, and the
+///   packages of the variants next to it
+fn fill_digests(package: &mut FlipbookPackage, path_binary: &str) -> Result<()> {
+    let in_own_package = |name: &String| package.variant_packages.contains_key(name);
+    let mut main: Vec<&mut FilePositionInPackage> = vec![];
+    let mut variants: BTreeMap<VariantName, Vec<&mut FilePositionInPackage>> = BTreeMap::new();
+    for page in &mut package.pages {
+        main.extend(&mut page.image);
+        for (name, variant) in &mut page.variants {
+            if in_own_package(name) {
+                variants.entry(name.clone()).or_default().push(variant);
+            } else {
+                main.push(variant);
+            }
+        }
+    }
+    main.extend(package.audio.values_mut());
+
+    fill_binary(path_binary, main, &mut package.binary_package_digest)?;
+    for (name, variant_package) in &mut package.variant_packages {
+        let path = variant_package.path_next_to(Path::new(path_binary));
+        let path = path.to_string_lossy();
+        if !Path::new(path.as_ref()).is_file() {
+            tracing::warn!(
+                "Binary package of the variant `{}` not found: {}",
+                name,
+                path
+            );
+            continue;
+        }
+        let fips = variants.remove(name).unwrap_or_default();
+        fill_binary(&path, fips, &mut variant_package.digest)?;
+    }
+    Ok(())
+}
+
+/// Fills the digests of `fips`, all in the binary package under `path_binary`, and `digest`, the
+///   one of the whole package
+fn fill_binary(
+    path_binary: &str,
+    fips: Vec<&mut FilePositionInPackage>,
+    digest: &mut Option<Digest>,
+) -> Result<()> {
+    let mut f = File::open(path_binary).map_err(read_error(path_binary))?;
+    let length = f.metadata().map_err(read_error(path_binary))?.len();
+
+    for fip in fips {
+        if fip.digest.is_none() && fip.start.saturating_add(fip.length) <= length {
+            let found =
+                hash_range(&mut f, fip.start, fip.length).map_err(read_error(path_binary))?;
+            fip.digest = Some(found);
+        }
+    }
+    if digest.is_none() {
+        *digest = Some(hash_range(&mut f, 0, length).map_err(read_error(path_binary))?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1: &str = r#"{
+        "version": 1,
+        "languages": ["en"],
+        "default_language": "en",
+        "binary_package_url": "fb_000.bin",
+        "texts": { "TITLE_en": "Title", "SUMMARY_en": "Summary", "PAGE_1_en": "one", "PAGE_2_en": "two" },
+        "audio": { "PAGE_1_en": { "format": "ogg", "start": 8, "length": 4 } },
+        "title": "TITLE_en",
+        "summary": "SUMMARY_en",
+        "miniature": "",
+        "images_in_pages": [
+            { "format": "jpg", "start": 0, "length": 4 },
+            { "format": "jpg", "start": 4, "length": 4 }
+        ]
+    }"#;
+
+    #[test]
+    fn upgrades_v1() {
+        let package = from_json(V1, "v1.json").unwrap();
+        assert_eq!(package.version, CURRENT_VERSION);
+
+        // Page 2 has text but no image
+        assert_eq!(package.pages.len(), 3);
+        assert_eq!(package.pages[1].image.as_ref().unwrap().start, 4);
+        assert_eq!(package.pages[1].texts["en"], "PAGE_1_en");
+        assert_eq!(package.pages[1].audio["en"], "PAGE_1_en");
+        assert!(package.pages[2].image.is_none());
+        assert_eq!(package.pages[2].texts["en"], "PAGE_2_en");
+    }
+
+    /// As the first v2 packages were written: no fallbacks, markup, timings, blocks nor variants
+    const EARLY_V2: &str = r#"{
+        "version": 2,
+        "languages": ["en"],
+        "default_language": "en",
+        "binary_package_url": "fb_000.bin",
+        "texts": { "TITLE_en": "Title", "SUMMARY_en": "Summary", "PAGE_1_en": "one" },
+        "audio": { "PAGE_1_en": { "format": "ogg", "start": 4, "length": 4 } },
+        "title": "TITLE_en",
+        "summary": "SUMMARY_en",
+        "miniature": "",
+        "pages": [
+            { "image": { "format": "jpg", "start": 0, "length": 4 }, "texts": {}, "audio": {} },
+            { "image": null, "texts": { "en": "PAGE_1_en" }, "audio": { "en": "PAGE_1_en" } }
+        ]
+    }"#;
+
+    #[test]
+    fn reads_early_v2() {
+        let package = from_json(EARLY_V2, "v2.json").unwrap();
+        assert_eq!(package.pages.len(), 2);
+        assert_eq!(package.pages[1].audio["en"], "PAGE_1_en");
+        assert!(package.fallbacks.is_empty() && package.markup.is_empty());
+        assert!(package.timings.is_empty() && package.variant_packages.is_empty());
+        assert!(package.pages[0].blocks.is_empty() && package.pages[0].variants.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let future = V1.replacen("\"version\": 1", "\"version\": 999", 1);
+        assert!(matches!(
+            from_json(&future, "future.json"),
+            Err(FlipbookError::UnsupportedPackageVersion { version: 999, .. })
+        ));
+    }
+}
@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+
+use crate::flipbook::common::{LanguageCode, MetadataVersion};
+
+use super::{
+    AudioDB, Base64Image, BinaryPackageURL, Digest, FilePositionInPackage, FlipbookPackageV2,
+    PageInPackage, StringID, TextDB, CURRENT_VERSION,
+};
+
+/// The original layout of the metadata: a page only exists through its image in `images_in_pages`
+///   and its texts and audio are found by convention, `PAGE_<page no.>_<lang>`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FlipbookPackageV1 {
+    pub version: MetadataVersion,
+    pub languages: Vec<LanguageCode>,
+    pub default_language: LanguageCode,
+    pub binary_package_url: BinaryPackageURL,
+    /// Only present in the last packages emitted as version 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_package_digest: Option<Digest>,
+    pub texts: TextDB,
+    pub audio: AudioDB,
+    pub title: StringID,
+    pub summary: StringID,
+    pub miniature: Base64Image,
+    pub images_in_pages: Vec<FilePositionInPackage>,
+    /// Only present in the last packages emitted as version 1, when found it's the source of truth
+    #[serde(default)]
+    pub pages: Vec<PageInPackage>,
+}
+
+impl FlipbookPackageV1 {
+    /// Rebuilds the per page record out of `images_in_pages` and the StringID convention
+    fn reconstruct_pages(&self) -> Vec<PageInPackage> {
+        let mut pages: Vec<PageInPackage> = self
+            .images_in_pages
+            .iter()
+            .map(|image| PageInPackage {
+                image: Some(image.clone()),
+                ..Default::default()
+            })
+            .collect();
+
+        for sid in self.texts.keys() {
+            if let Some((page, lang)) = parse_page_string_id(sid) {
+                page_entry(&mut pages, page)
+                    .texts
+                    .insert(lang.to_string(), sid.clone());
+            }
+        }
+        for sid in self.audio.keys() {
+            if let Some((page, lang)) = parse_page_string_id(sid) {
+                page_entry(&mut pages, page)
+                    .audio
+                    .insert(lang.to_string(), sid.clone());
+            }
+        }
+        pages
+    }
+}
+
+/// Texts on pages without image can exist, the pages are extended up to them
+fn page_entry(pages: &mut Vec<PageInPackage>, page: usize) -> &mut PageInPackage {
+    if pages.len() <= page {
+        pages.resize_with(page + 1, PageInPackage::default);
+    }
+    &mut pages[page]
+}
+
+/// `PAGE_<page no.>_<lang>` -> (page no., lang)
+fn parse_page_string_id(sid: &str) -> Option<(usize, &str)> {
+    let (page, lang) = sid.strip_prefix("PAGE_")?.split_once('_')?;
+    Some((page.parse().ok()?, lang))
+}
+
+impl From<FlipbookPackageV1> for FlipbookPackageV2 {
+    fn from(mut v1: FlipbookPackageV1) -> Self {
+        let pages = if v1.pages.is_empty() {
+            v1.reconstruct_pages()
+        } else {
+            std::mem::take(&mut v1.pages)
+        };
+
+        Self {
+            version: CURRENT_VERSION,
+            languages: v1.languages,
+            default_language: v1.default_language,
+            binary_package_url: v1.binary_package_url,
+            binary_package_digest: v1.binary_package_digest,
+            texts: v1.texts,
+            audio: v1.audio,
+            title: v1.title,
+            summary: v1.summary,
+            miniature: v1.miniature,
+            pages,
+            variant_packages: Default::default(),
+            timings: Default::default(),
+            markup: Default::default(),
+            fallbacks: Default::default(),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::flipbook::common::{LanguageCode, MetadataVersion};
+
+use super::{
+    AudioDB, Base64Image, BinaryPackageURL, Digest, PageInPackage, StringID, TextDB, TimingDB,
+    VariantName, VariantPackage,
+};
+
+/// This is the "high level item" that the client will use to allow the user select which flipbook is going to be "played"
+///   Every field added after the first v2 packages is optional, see the versions in `package`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FlipbookPackageV2 {
+    /// Version of this data definition (it's possible to predict the format that's incoming)
+    pub version: MetadataVersion,
+    pub languages: Vec<LanguageCode>,
+    pub default_language: LanguageCode,
+    /// As declared in the source. The pages already point at the texts of the fallbacks, the
+    ///   title and summary are left to the client, see `PackageReader::title`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fallbacks: BTreeMap<LanguageCode, Vec<LanguageCode>>,
+    // ---- flipbook resources link
+    pub binary_package_url: BinaryPackageURL,
+    /// Digest of the whole binary package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary_package_digest: Option<Digest>,
+    /// See note for StringID but I'm suggesting something like: ID_00_en
+    pub texts: TextDB,
+    /// The texts written with markup, normalised, under the same StringIDs. `texts` has them as
+    ///   plain text, for the clients that don't draw it. See `crate::flipbook::markup`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub markup: TextDB,
+    pub audio: AudioDB,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub timings: TimingDB,
+
+    // ---- This is more "natural data" of the object
+    pub title: StringID,
+    pub summary: StringID,
+    /// This is an embedded image in the payload of the Package metadata. The idea is that when the
+    pub miniature: Base64Image,
+
+    /// One entry per page of the source
+    pub pages: Vec<PageInPackage>,
+    /// Variants with a binary package of their own, see `PageInPackage::variants`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variant_packages: BTreeMap<VariantName, VariantPackage>,
+}
@@ -1,21 +1,31 @@
 #![allow(dead_code)]
 
 // Taking some ideas from logSeq: #lillaOrd-flipbook-compiler
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
+use crate::error::{FlipbookError, Result};
+
 use super::common::{FilePath, LanguageCode, MetadataVersion, RawString};
+use super::language;
+use super::layout::{Rect, TextAlign};
+use super::timing::TimingFile;
 
 /// This structure points at the idea that audio is secondary to text
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Asset {
     pub text: RawString,
     pub audio: Option<Audio>,
+    /// When every word, or sentence, of `text` is said in `audio`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timings: Vec<TimingFile>,
 }
 
+/// Ordered by language so the same source always compiles to the same package
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
-pub struct PageText(pub HashMap<LanguageCode, Asset>);
+pub struct PageText(pub BTreeMap<LanguageCode, Asset>);
 
 impl PageText {
     pub fn texts(&self) -> Vec<(LanguageCode, RawString)> {
@@ -46,49 +56,295 @@ pub struct Audio {
     pub path: FilePath,
 }
 
+/// A text box at a fixed place of the page, see `layout`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TextBlock {
+    pub rect: Rect,
+    #[serde(default)]
+    pub align: TextAlign,
+    /// Height of a line, as a fraction of the page height. `None` leaves it to the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f32>,
+    pub text: PageText,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct SourcePage {
     pub background: Image,
     // The cover page for example has no text at all
     pub text: Option<PageText>,
+    /// Drawn where they say, `text` is left for the client to place
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<TextBlock>,
 }
 
+/// Where a text of the pages is: the text of a page, `block: None`, or one of its blocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextLocation {
+    pub page: usize,
+    pub block: Option<usize>,
+}
+
+/// The version sources are written with, `package::CURRENT_VERSION` is the one of compiled books
+pub const SOURCE_VERSION: MetadataVersion = 1;
+
 /// Root V1 source structure
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct FlipbookSource {
     pub version: MetadataVersion,
     pub languages: Vec<LanguageCode>,
     pub default_language: LanguageCode,
+    /// Where the texts missing in a language are taken from, in order: `"sv": ["en"]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fallbacks: BTreeMap<LanguageCode, Vec<LanguageCode>>,
 
     pub title: PageText,
     pub summary: PageText,
-    pub miniature: Image,
+    /// `None` derives the miniature from the background of the first page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub miniature: Option<Image>,
 
     pub pages: Vec<SourcePage>,
+
+    /// Directory the relative paths of the assets are resolved against, see `resolve`. Not part
+    ///   of the JSON: `from_file` sets it to the directory holding the source
+    #[serde(skip)]
+    pub asset_root: Option<PathBuf>,
 }
 
 impl FlipbookSource {
-    pub fn pages_text(&self) -> Vec<(usize, LanguageCode, RawString)> {
+    /// Reads and deserializes a source flipbook stored as JSON. Its relative paths are resolved
+    ///   against the directory holding it
+    pub fn from_file(path: &str) -> Result<Self> {
+        let read_error = |source| FlipbookError::SourceRead {
+            path: path.to_string(),
+            source,
+        };
+        let content = std::fs::read_to_string(path).map_err(read_error)?;
+        let mut source: Self =
+            serde_json::from_str(&content).map_err(|source| FlipbookError::InvalidSource {
+                path: path.to_string(),
+                source,
+            })?;
+
+        // `source.json` has an empty parent, the working directory
+        let directory = match Path::new(path).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        source.asset_root = Some(std::path::absolute(directory).map_err(read_error)?);
+        Ok(source)
+    }
+
+    /// Same as `from_file` but resolving the relative paths against `asset_root` instead
+    pub fn from_file_with_asset_root(path: &str, asset_root: &str) -> Result<Self> {
+        let mut source = Self::from_file(path)?;
+        source.asset_root =
+            Some(
+                std::path::absolute(asset_root).map_err(|source| FlipbookError::SourceRead {
+                    path: asset_root.to_string(),
+                    source,
+                })?,
+            );
+        Ok(source)
+    }
+
+    /// The image the miniature is made from, resolved: `miniature` or the background of the first
+    ///   page. `None` when there's neither
+    pub fn miniature_source(&self) -> Option<FilePath> {
+        let image = match &self.miniature {
+            Some(miniature) => miniature,
+            None => &self.pages.first()?.background,
+        };
+        Some(self.resolve(&image.path))
+    }
+
+    /// Where an asset of this source is: absolute paths are kept, relative ones are resolved
+    ///   against `asset_root`. Without `asset_root` they're left relative to the working directory
+    pub fn resolve(&self, path: &str) -> FilePath {
+        match &self.asset_root {
+            Some(root) if Path::new(path).is_relative() => {
+                root.join(path).to_string_lossy().to_string()
+            }
+            _ => path.to_string(),
+        }
+    }
+
+    /// The same source with every language written as its canonical tag, see `language`. Fails
+    ///   on languages that can't be told, or on two that turn out to be the same one
+    pub fn with_canonical_languages(&self) -> Result<Self> {
+        let mut source = self.clone();
+        source.languages = canonical_list(&self.languages, "`languages`")?;
+        source.default_language = canonical(&self.default_language)?;
+        if !source.languages.contains(&source.default_language) {
+            return Err(FlipbookError::InconsistentLanguages {
+                reason: format!(
+                    "the default language `{}` isn't one of `languages`",
+                    self.default_language
+                ),
+            });
+        }
+        source.fallbacks = BTreeMap::new();
+        for (lang, chain) in &self.fallbacks {
+            let chain = canonical_list(chain, &format!("the fallbacks of `{lang}`"))?;
+            if source.fallbacks.insert(canonical(lang)?, chain).is_some() {
+                return Err(same_language("`fallbacks`", lang));
+            }
+        }
+        source.title = canonical_text(&self.title)?;
+        source.summary = canonical_text(&self.summary)?;
+        for page in &mut source.pages {
+            if let Some(text) = &page.text {
+                page.text = Some(canonical_text(text)?);
+            }
+            for block in &mut page.blocks {
+                block.text = canonical_text(&block.text)?;
+            }
+        }
+        Ok(source)
+    }
+
+    /// `language` followed by its fallbacks
+    pub fn language_chain<'a>(&'a self, language: &'a str) -> impl Iterator<Item = &'a str> {
+        let fallbacks = self.fallbacks.get(language).map(Vec::as_slice);
+        std::iter::once(language).chain(fallbacks.unwrap_or_default().iter().map(String::as_str))
+    }
+
+    /// What a reader of `language` gets of `text`: its own translation or the one of the first
+    ///   fallback that has it. Along with the language it's written in
+    pub fn text_for<'a>(
+        &self,
+        text: &'a PageText,
+        language: &str,
+    ) -> Option<(&'a LanguageCode, &'a Asset)> {
+        self.language_chain(language)
+            .find_map(|lang| text.0.get_key_value(lang))
+    }
+
+    /// The text of every page followed by its blocks, in order
+    pub fn located_texts(&self) -> Vec<(TextLocation, &PageText)> {
         let mut answer = vec![];
         for (pos, page) in self.pages.iter().enumerate() {
             if let Some(texts_in_page) = &page.text {
-                for tip in texts_in_page.texts() {
-                    answer.push((pos, tip.0, tip.1));
-                }
+                let location = TextLocation {
+                    page: pos,
+                    block: None,
+                };
+                answer.push((location, texts_in_page));
+            }
+            for (block, text_block) in page.blocks.iter().enumerate() {
+                let location = TextLocation {
+                    page: pos,
+                    block: Some(block),
+                };
+                answer.push((location, &text_block.text));
             }
         }
         answer
     }
 
-    pub fn pages_audios(&self) -> Vec<(usize, LanguageCode, FilePath)> {
+    pub fn pages_text(&self) -> Vec<(TextLocation, LanguageCode, RawString)> {
         let mut answer = vec![];
-        for (pos, page) in self.pages.iter().enumerate() {
-            if let Some(texts_in_page) = &page.text {
-                for aip in texts_in_page.audios() {
-                    answer.push((pos, aip.0, aip.1));
+        for (location, texts) in self.located_texts() {
+            for tip in texts.texts() {
+                answer.push((location, tip.0, tip.1));
+            }
+        }
+        answer
+    }
+
+    /// The paths come resolved, see `resolve`
+    pub fn pages_timings(&self) -> Vec<(TextLocation, LanguageCode, TimingFile)> {
+        let mut answer = vec![];
+        for (location, texts) in self.located_texts() {
+            for (lang, asset) in &texts.0 {
+                for timing in &asset.timings {
+                    let resolved = TimingFile {
+                        path: self.resolve(&timing.path),
+                        ..timing.clone()
+                    };
+                    answer.push((location, lang.clone(), resolved));
                 }
             }
         }
         answer
     }
+
+    /// The paths come resolved, see `resolve`
+    pub fn pages_audios(&self) -> Vec<(TextLocation, LanguageCode, FilePath)> {
+        let mut answer = vec![];
+        for (location, texts) in self.located_texts() {
+            for aip in texts.audios() {
+                answer.push((location, aip.0, self.resolve(&aip.1)));
+            }
+        }
+        answer
+    }
+}
+
+fn canonical(code: &str) -> Result<LanguageCode> {
+    language::canonical(code).map_err(|source| FlipbookError::InvalidLanguage { source })
+}
+
+fn same_language(place: &str, code: &str) -> FlipbookError {
+    FlipbookError::InconsistentLanguages {
+        reason: format!("`{code}` is written twice in {place}, maybe with another name"),
+    }
+}
+
+fn canonical_list(codes: &[LanguageCode], place: &str) -> Result<Vec<LanguageCode>> {
+    let mut answer = vec![];
+    for code in codes {
+        let canonical = canonical(code)?;
+        if answer.contains(&canonical) {
+            return Err(same_language(place, code));
+        }
+        answer.push(canonical);
+    }
+    Ok(answer)
+}
+
+fn canonical_text(text: &PageText) -> Result<PageText> {
+    let mut answer = BTreeMap::new();
+    for (code, asset) in &text.0 {
+        if answer.insert(canonical(code)?, asset.clone()).is_some() {
+            return Err(same_language("a text", code));
+        }
+    }
+    Ok(PageText(answer))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{compile_source, sample_options, sample_source, scratch_dir};
+
+    #[test]
+    fn missing_translations_point_at_their_fallback() {
+        let dir = scratch_dir("fallbacks");
+        let mut source = sample_source(&dir);
+        source.languages.push("sv".to_string());
+        source
+            .fallbacks
+            .insert("sv".to_string(), vec!["en".to_string()]);
+        let page_1 = source.pages[1].text.as_mut().unwrap();
+        page_1.0.insert(
+            "sv".to_string(),
+            serde_json::from_value(serde_json::json!({ "text": "ett" })).unwrap(),
+        );
+        assert_eq!(
+            source.language_chain("sv").collect::<Vec<_>>(),
+            ["sv", "en"]
+        );
+
+        let reader = compile_source(&dir, &source, &sample_options()).unwrap();
+        let package = reader.package();
+        assert_eq!(package.fallbacks["sv"], vec!["en"]);
+        assert_eq!(package.pages[1].texts["sv"], "PAGE_1_sv");
+        assert_eq!(package.pages[2].texts["sv"], "PAGE_2_en");
+        assert_eq!(package.pages[2].audio["sv"], "PAGE_2_en");
+        assert!(!package.texts.contains_key("PAGE_2_sv"));
+        assert_eq!(reader.text(2, "sv").unwrap(), "two");
+        assert_eq!(reader.title("sv").unwrap(), "Title");
+        assert!(reader.title("de").is_none());
+    }
 }
@@ -0,0 +1,407 @@
+// Timing tracks tell when every word, or sentence, of a text is said in its narration, so the
+//   client can highlight the text as the audio plays.
+// Authors don't write them by hand: they come from subtitle editors or from Audacity, where the
+//   narration is already open. The three formats are read here into the same `TimingTrack`.
+use std::fmt::Display;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AssetContext, FlipbookError, Result};
+
+use super::common::{FilePath, RawString};
+
+/// Cues may end this much after their audio, timestamps are rounded to the millisecond
+pub const TIMING_TOLERANCE_MS: u64 = 50;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CueKind {
+    #[default]
+    Word,
+    Sentence,
+}
+
+impl CueKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Word => "word",
+            Self::Sentence => "sentence",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimingFormat {
+    WebVtt,
+    Srt,
+    /// Label track exported by Audacity: `<start>\t<end>\t<label>`, in seconds
+    Audacity,
+}
+
+impl TimingFormat {
+    /// `.vtt`, `.srt` or `.txt`, the extension Audacity exports labels with
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "vtt" => Some(Self::WebVtt),
+            "srt" => Some(Self::Srt),
+            "txt" => Some(Self::Audacity),
+            _ => None,
+        }
+    }
+}
+
+/// A timing track of the source, to be read while compiling
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TimingFile {
+    pub path: FilePath,
+    #[serde(default)]
+    pub kind: CueKind,
+    /// `None` tells it from the extension, see `TimingFormat::from_path`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<TimingFormat>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Cue {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: RawString,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TimingTrack {
+    pub kind: CueKind,
+    /// In the order they appear in the file
+    pub cues: Vec<Cue>,
+}
+
+impl TimingTrack {
+    /// Where the last cue ends
+    pub fn end_ms(&self) -> u64 {
+        self.cues.iter().map(|c| c.end_ms).max().unwrap_or_default()
+    }
+
+    /// As a WebVTT file, the way `decompile` gives it back
+    pub fn to_webvtt(&self) -> String {
+        let mut vtt = String::from("WEBVTT\n");
+        for cue in &self.cues {
+            vtt.push_str(&format!(
+                "\n{} --> {}\n{}\n",
+                format_timestamp(cue.start_ms),
+                format_timestamp(cue.end_ms),
+                cue.text
+            ));
+        }
+        vtt
+    }
+}
+
+/// Why a timing file can't be read, `line` counts from 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimingParseError {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl Display for TimingParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for TimingParseError {}
+
+type ParseResult<T> = std::result::Result<T, TimingParseError>;
+
+/// Reads the timing track `file` points at, its path already resolved. `context` is the one of
+///   the file
+pub fn load(file: &TimingFile, context: &AssetContext) -> Result<TimingTrack> {
+    let format = file
+        .format
+        .or_else(|| TimingFormat::from_path(&file.path))
+        .ok_or_else(|| FlipbookError::UnsupportedFormat {
+            context: context.clone(),
+        })?;
+    let content = std::fs::read_to_string(&file.path)
+        .map_err(|e| FlipbookError::from_asset_read(context.clone(), e))?;
+    parse(&content, format, file.kind).map_err(|source| FlipbookError::InvalidTiming {
+        context: context.clone(),
+        source,
+    })
+}
+
+/// Fails when `track` doesn't fit in an audio of `duration_ms`
+pub fn check_duration(track: &TimingTrack, duration_ms: u64, context: &AssetContext) -> Result<()> {
+    let end_ms = track.end_ms();
+    if end_ms > duration_ms + TIMING_TOLERANCE_MS {
+        return Err(FlipbookError::TimingOutOfRange {
+            context: context.clone(),
+            end_ms,
+            duration_ms,
+        });
+    }
+    Ok(())
+}
+
+fn parse_error(line: usize, reason: impl Into<String>) -> TimingParseError {
+    TimingParseError {
+        line,
+        reason: reason.into(),
+    }
+}
+
+pub fn parse(content: &str, format: TimingFormat, kind: CueKind) -> ParseResult<TimingTrack> {
+    let cues = match format {
+        TimingFormat::WebVtt => parse_webvtt(content)?,
+        TimingFormat::Srt => parse_blocks(content)?,
+        TimingFormat::Audacity => parse_audacity(content)?,
+    };
+    Ok(TimingTrack { kind, cues })
+}
+
+/// `HH:MM:SS.mmm`, `MM:SS.mmm` or `SS.mmm`, with `.` or `,` before the milliseconds
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (clock, millis) = timestamp.trim().split_once(['.', ','])?;
+    if millis.len() != 3 {
+        return None;
+    }
+    let mut seconds = 0u64;
+    for part in clock.split(':') {
+        seconds = seconds
+            .checked_mul(60)?
+            .checked_add(part.parse::<u64>().ok()?)?;
+    }
+    seconds
+        .checked_mul(1000)?
+        .checked_add(millis.parse::<u64>().ok()?)
+}
+
+fn format_timestamp(ms: u64) -> String {
+    let (seconds, millis) = (ms / 1000, ms % 1000);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        millis
+    )
+}
+
+/// `<start> --> <end>`, WebVTT may add settings after `<end>`
+fn parse_time_range(line: &str, line_no: usize) -> ParseResult<(u64, u64)> {
+    let (start, rest) = line
+        .split_once("-->")
+        .ok_or_else(|| parse_error(line_no, "expected `<start> --> <end>`"))?;
+    let end = rest.split_whitespace().next().unwrap_or_default();
+    match (parse_timestamp(start), parse_timestamp(end)) {
+        (Some(start), Some(end)) if start <= end => Ok((start, end)),
+        (Some(_), Some(_)) => Err(parse_error(line_no, "the cue ends before it starts")),
+        _ => Err(parse_error(line_no, format!("invalid time range `{line}`"))),
+    }
+}
+
+/// WebVTT markup, `<b>`, `<c.yellow>`, `<00:00:01.000>`, .. isn't part of the text
+fn strip_tags(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    plain
+}
+
+fn parse_webvtt(content: &str) -> ParseResult<Vec<Cue>> {
+    let content = content.trim_start_matches('\u{feff}');
+    if !content.starts_with("WEBVTT") {
+        return Err(parse_error(1, "a WebVTT file starts with `WEBVTT`"));
+    }
+    parse_blocks(content)
+}
+
+/// SRT and WebVTT share the shape: blocks separated by blank lines, each with an optional
+///   identifier, a time range and the text. Blocks without a time range (the WebVTT header,
+///   `NOTE`, `STYLE`, ..) are skipped
+fn parse_blocks(content: &str) -> ParseResult<Vec<Cue>> {
+    let mut cues = vec![];
+    let mut lines = content.lines().enumerate().peekable();
+    while lines.peek().is_some() {
+        let block: Vec<(usize, &str)> = lines
+            .by_ref()
+            .skip_while(|(_, l)| l.trim().is_empty())
+            .take_while(|(_, l)| !l.trim().is_empty())
+            .collect();
+        let Some(range_pos) = block.iter().position(|(_, l)| l.contains("-->")) else {
+            continue;
+        };
+        let (line_no, range) = block[range_pos];
+        let (start_ms, end_ms) = parse_time_range(range, line_no + 1)?;
+        let text: Vec<String> = block[range_pos + 1..]
+            .iter()
+            .map(|(_, l)| strip_tags(l.trim()))
+            .collect();
+        cues.push(Cue {
+            start_ms,
+            end_ms,
+            text: text.join(" "),
+        });
+    }
+    Ok(cues)
+}
+
+fn parse_audacity(content: &str) -> ParseResult<Vec<Cue>> {
+    let mut cues = vec![];
+    for (line_no, line) in content.lines().enumerate() {
+        // Lines starting with `\` carry the frequencies of a spectral selection
+        if line.trim().is_empty() || line.starts_with('\\') {
+            continue;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let seconds = |field: Option<&str>| -> ParseResult<u64> {
+            let value: f64 = field
+                .and_then(|f| f.trim().parse().ok())
+                .filter(|v: &f64| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| parse_error(line_no + 1, "expected `<start>\t<end>\t<label>`"))?;
+            Ok((value * 1000.0).round() as u64)
+        };
+        let start_ms = seconds(fields.next())?;
+        let end_ms = seconds(fields.next())?;
+        if end_ms < start_ms {
+            return Err(parse_error(line_no + 1, "the cue ends before it starts"));
+        }
+        cues.push(Cue {
+            start_ms,
+            end_ms,
+            text: fields.next().unwrap_or_default().trim().to_string(),
+        });
+    }
+    Ok(cues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start_ms: u64, end_ms: u64, text: &str) -> Cue {
+        Cue {
+            start_ms,
+            end_ms,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_every_format_alike() {
+        let expected = vec![cue(500, 900, "Once"), cue(1000, 62250, "upon a time")];
+
+        let vtt = "WEBVTT - a story\n\nNOTE written by hand\n\n1\n00:00.500 --> 00:00.900 \
+                   align:start\n<b>Once</b>\n\n00:00:01.000 --> 00:01:02.250\nupon\na time\n";
+        let track = parse(vtt, TimingFormat::WebVtt, CueKind::Word).unwrap();
+        assert_eq!(track.cues, expected);
+        assert_eq!(track.end_ms(), 62250);
+        assert_eq!(
+            parse(&track.to_webvtt(), TimingFormat::WebVtt, CueKind::Word).unwrap(),
+            track
+        );
+
+        let srt = "1\r\n00:00:00,500 --> 00:00:00,900\r\nOnce\r\n\r\n2\r\n00:00:01,000 --> \
+                   00:01:02,250\r\nupon a time\r\n";
+        assert_eq!(
+            parse(srt, TimingFormat::Srt, CueKind::Word).unwrap().cues,
+            expected
+        );
+
+        let labels = "0.5\t0.9\tOnce\n\\\t100.0\t2000.0\n1.000000\t62.250000\tupon a time\n";
+        let track = parse(labels, TimingFormat::Audacity, CueKind::Sentence).unwrap();
+        assert_eq!((track.kind, track.cues), (CueKind::Sentence, expected));
+
+        assert_eq!(
+            parse(
+                "1\n00:00:02,000 --> 00:00:01,000\nback",
+                TimingFormat::Srt,
+                CueKind::Word
+            ),
+            Err(parse_error(2, "the cue ends before it starts"))
+        );
+        assert!(parse("1\n00:00:01,000\nOnce", TimingFormat::WebVtt, CueKind::Word).is_err());
+        // Doesn't fit in milliseconds
+        assert_eq!(
+            parse(
+                "1\n00:00:00,000 --> 99999999999999999:00,000\nlate",
+                TimingFormat::Srt,
+                CueKind::Word
+            ),
+            Err(parse_error(
+                2,
+                "invalid time range `00:00:00,000 --> 99999999999999999:00,000`"
+            ))
+        );
+    }
+
+    #[test]
+    fn timings_fit_in_their_audio() {
+        use crate::test_utils::{compile_source, sample_options, sample_source, scratch_dir};
+
+        let dir = scratch_dir("timings");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(dir.join("02_en.wav"), spec).unwrap();
+        for _ in 0..8000 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        std::fs::write(dir.join("02_en.txt"), "0.1\t0.5\ttwo\n").unwrap();
+        std::fs::write(
+            dir.join("01_en.srt"),
+            "1\n00:00:00,000 --> 00:00:05,000\none\n",
+        )
+        .unwrap();
+
+        let path = |f: &str| dir.join(f).to_str().unwrap().to_string();
+        let timing = |f: &str| TimingFile {
+            path: path(f),
+            kind: CueKind::Word,
+            format: None,
+        };
+        let mut source = sample_source(&dir);
+        let page_1 = source.pages[1].text.as_mut().unwrap();
+        // Without audio to check it against
+        page_1.0.get_mut("en").unwrap().timings = vec![timing("01_en.srt")];
+        let page_2 = source.pages[2]
+            .text
+            .as_mut()
+            .unwrap()
+            .0
+            .get_mut("en")
+            .unwrap();
+        page_2.audio.as_mut().unwrap().path = path("02_en.wav");
+        page_2.timings = vec![timing("02_en.txt")];
+
+        let reader = compile_source(&dir, &source, &sample_options()).unwrap();
+        let package = reader.package();
+        let track = &package.timings["PAGE_2_en"][0];
+        assert_eq!((track.cues[0].start_ms, track.end_ms()), (100, 500));
+        assert_eq!(package.timings["PAGE_1_en"][0].end_ms(), 5000);
+
+        std::fs::write(dir.join("02_en.txt"), "0.1\t0.5\ttwo\n0.5\t1.5\tpages\n").unwrap();
+        assert!(matches!(
+            compile_source(&dir, &source, &sample_options()),
+            Err(FlipbookError::TimingOutOfRange {
+                end_ms: 1500,
+                duration_ms: 1000,
+                ..
+            })
+        ));
+    }
+}
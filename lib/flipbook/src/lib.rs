@@ -1,4 +1,14 @@
 // This `lib.rs` file is consumed, at least by `mock-flipbook`
 
+pub mod batch;
 pub mod compile;
+pub mod container;
+pub mod diff;
+pub mod error;
 pub mod flipbook;
+pub mod reader;
+pub mod validate;
+pub mod verify;
+
+#[cfg(test)]
+mod test_utils;
@@ -1,36 +1,101 @@
 #![deny(clippy::all)]
 #![warn(clippy::nursery, clippy::pedantic)]
 
-use std::path::Path;
-
 use anyhow::Result;
+use clap::Parser;
 
-mod compile;
-mod flipbook;
-
-use crate::flipbook::source::FlipbookSource;
+mod args;
+mod commands;
+use args::{Args, Command};
 
-// Let's nail a format for the files
-pub fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
-    tracing::info!("Hardcoded flipbook compilation example");
+fn main() -> Result<()> {
+    // stdout is kept for the `--json` reports
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
 
-    // Let's use the file `sample_source.json`
-    let path = "./test_source/sample_source.json";
-    tracing::info!("Reading from: {}", path);
-    let p = Path::new(path);
-    assert!(p.exists());
+    let args = Args::parse();
+    tracing::debug!("Arguments read: {:#?}", args);
+    run(&args)
+}
 
-    let content = std::fs::read_to_string(path)?;
+fn run(args: &Args) -> Result<()> {
+    match &args.command {
+        Command::Compile(compile) => commands::compile(compile, args.json),
+        Command::Batch(batch) => commands::batch(batch, args.json),
+        Command::Validate {
+            source,
+            asset_root,
+            missing_background,
+            strict_formats,
+        } => commands::validate(
+            source,
+            asset_root.as_deref(),
+            *missing_background,
+            *strict_formats,
+            args.json,
+        ),
+        Command::Inspect { input } => commands::inspect(input, args.json),
+        Command::Extract {
+            input,
+            page,
+            language,
+            output,
+        } => commands::extract(input, *page, language.as_deref(), output, args.json),
+        Command::Decompile { input, output } => commands::decompile(input, output, args.json),
+        Command::Diff { left, right } => commands::diff(left, right, args.json),
+    }
+}
 
-    let source: FlipbookSource = serde_json::from_str(&content)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let path_metadata = "./test_output/compiled_sample_source.json";
-    let path_binary = "./test_output/compiled_sample_source.bin";
+    fn run_with(args: &[&str]) -> Result<()> {
+        run(&Args::try_parse_from([&["flipbook"], args].concat())?)
+    }
 
-    compile::compile(&source, path_metadata, path_binary)?;
+    #[test]
+    fn missing_background_policy_applies_to_validation() {
+        let dir = std::env::temp_dir().join(format!("flipbook-cli-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::from_pixel(64, 48, image::Rgb([200, 120, 40]))
+            .save(dir.join("00.jpg"))
+            .unwrap();
+        let source = dir.join("source.json");
+        std::fs::write(
+            &source,
+            r#"{
+                "version": 1,
+                "languages": ["en"],
+                "default_language": "en",
+                "title": { "en": { "text": "Title" } },
+                "summary": { "en": { "text": "Summary" } },
+                "pages": [
+                    { "background": { "path": "00.jpg" } },
+                    { "background": { "path": "01.jpg" }, "text": { "en": { "text": "one" } } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let source = source.to_str().unwrap();
+        let output = dir.join("out.json");
+        let output = output.to_str().unwrap();
 
-    tracing::info!("Metadata generated: {}", path_metadata);
-    tracing::info!("Binary generated: {}", path_binary);
-    Ok(())
+        assert!(run_with(&["validate", source]).is_err());
+        assert!(run_with(&["compile", source, "--output", output]).is_err());
+        for policy in ["leave-empty", "placeholder"] {
+            run_with(&["validate", source, "--missing-background", policy]).unwrap();
+            run_with(&[
+                "compile",
+                source,
+                "--output",
+                output,
+                "--missing-background",
+                policy,
+            ])
+            .unwrap();
+        }
+    }
 }
@@ -0,0 +1,353 @@
+// The `reader` module is the way back from a compiled flipbook: it opens the metadata and the
+//   assets (split or as a container) and answers what the client asks for, "the image of page 3"
+//   or "the Swedish audio of page 5", without anybody poking at offsets by hand.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use base64::{engine::general_purpose, Engine};
+
+use crate::container;
+use crate::error::{FlipbookError, Result};
+use crate::flipbook::common::{LanguageCode, RawString};
+use crate::flipbook::format::BinaryFormat;
+use crate::flipbook::package::{
+    self, FilePositionInPackage, FlipbookPackage, PageInPackage, VariantName,
+};
+
+/// Random access to the assets of a compiled flipbook, either a binary package or the asset
+///   section of a container
+pub struct AssetReader {
+    file: File,
+    /// Used to give context to the errors
+    path: String,
+    /// Where the assets start in `file`
+    base: u64,
+    length: u64,
+}
+
+impl AssetReader {
+    pub(crate) fn new(file: File, path: &str, base: u64, length: u64) -> Self {
+        Self {
+            file,
+            path: path.to_string(),
+            base,
+            length,
+        }
+    }
+
+    /// The binary package of the split format
+    pub fn open_binary(path: &str) -> Result<Self> {
+        let read_error = |source| FlipbookError::PackageRead {
+            path: path.to_string(),
+            source,
+        };
+        let file = File::open(path).map_err(read_error)?;
+        let length = file.metadata().map_err(read_error)?.len();
+        Ok(Self::new(file, path, 0, length))
+    }
+
+    /// Length of the asset section, the offsets of `FilePositionInPackage` go up to it
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Fails when the asset doesn't fall inside the asset section
+    pub fn check_range(&self, fip: &FilePositionInPackage) -> Result<()> {
+        let end = fip.start.checked_add(fip.length);
+        if matches!(end, Some(end) if end <= self.length) {
+            Ok(())
+        } else {
+            Err(FlipbookError::AssetOutOfRange {
+                path: self.path.clone(),
+                start: fip.start,
+                length: fip.length,
+                package_length: self.length,
+            })
+        }
+    }
+
+    /// Reads the bytes of an asset, failing if they fall outside the asset section
+    pub fn read(&mut self, fip: &FilePositionInPackage) -> Result<Vec<u8>> {
+        self.check_range(fip)?;
+
+        let read_error = |source| FlipbookError::PackageRead {
+            path: self.path.clone(),
+            source,
+        };
+        self.file
+            .seek(SeekFrom::Start(self.base + fip.start))
+            .map_err(read_error)?;
+        let mut content = vec![0u8; fip.length as usize];
+        self.file.read_exact(&mut content).map_err(read_error)?;
+        Ok(content)
+    }
+}
+
+/// An asset as read from the package
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadedAsset {
+    pub format: BinaryFormat,
+    pub content: Vec<u8>,
+}
+
+/// A compiled flipbook opened for reading. Every range in the metadata is checked against the
+///   assets when opening, so reading an asset only fails on I/O errors
+pub struct PackageReader {
+    package: FlipbookPackage,
+    assets: AssetReader,
+    /// The binary packages of the variants with one of their own, see `variant`
+    variants: BTreeMap<VariantName, AssetReader>,
+    /// Used to give context to the errors
+    path: String,
+}
+
+impl PackageReader {
+    /// Opens a metadata + binary package pair
+    pub fn open(path_metadata: &str, path_binary: &str) -> Result<Self> {
+        let package = package::load(path_metadata)?;
+        let assets = AssetReader::open_binary(path_binary)?;
+        let variants = open_variants(&package, Path::new(path_binary))?;
+        Self::new(package, assets, variants, path_metadata)
+    }
+
+    /// Opens a metadata file, looking for its binary package next to it through
+    ///   `binary_package_url`
+    pub fn open_metadata(path_metadata: &str) -> Result<Self> {
+        let package = package::load(path_metadata)?;
+        let path_binary = Path::new(path_metadata)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&package.binary_package_url);
+        let assets = AssetReader::open_binary(&path_binary.to_string_lossy())?;
+        let variants = open_variants(&package, &path_binary)?;
+        Self::new(package, assets, variants, path_metadata)
+    }
+
+    /// Opens a single file flipbook, see `crate::container`
+    pub fn open_container(path: &str) -> Result<Self> {
+        let (package, assets) = container::open(path)?;
+        let variants = open_variants(&package, Path::new(path))?;
+        Self::new(package, assets, variants, path)
+    }
+
+    fn new(
+        package: FlipbookPackage,
+        assets: AssetReader,
+        variants: BTreeMap<VariantName, AssetReader>,
+        path: &str,
+    ) -> Result<Self> {
+        for page in &package.pages {
+            if let Some(image) = &page.image {
+                assets.check_range(image)?;
+            }
+            for (name, variant) in &page.variants {
+                variants.get(name).unwrap_or(&assets).check_range(variant)?;
+            }
+        }
+        for audio in package.audio.values() {
+            assets.check_range(audio)?;
+        }
+        Ok(Self {
+            package,
+            assets,
+            variants,
+            path: path.to_string(),
+        })
+    }
+
+    pub fn package(&self) -> &FlipbookPackage {
+        &self.package
+    }
+
+    pub fn languages(&self) -> &[LanguageCode] {
+        &self.package.languages
+    }
+
+    pub fn default_language(&self) -> &str {
+        &self.package.default_language
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.package.pages.len()
+    }
+
+    pub fn page(&self, page: usize) -> Option<&PageInPackage> {
+        self.package.pages.get(page)
+    }
+
+    /// In `language` or, when it has none, in the first of its fallbacks that has one
+    pub fn title(&self, language: &str) -> Option<&RawString> {
+        self.in_language("TITLE", language)
+    }
+
+    /// See `title`
+    pub fn summary(&self, language: &str) -> Option<&RawString> {
+        self.in_language("SUMMARY", language)
+    }
+
+    /// The text `<prefix>_<lang>` of `language` or its fallbacks
+    fn in_language(&self, prefix: &str, language: &str) -> Option<&RawString> {
+        let fallbacks = self.package.fallbacks.get(language).map(Vec::as_slice);
+        std::iter::once(language)
+            .chain(fallbacks.unwrap_or_default().iter().map(String::as_str))
+            .find_map(|lang| self.package.texts.get(&format!("{prefix}_{lang}")))
+    }
+
+    pub fn text(&self, page: usize, language: &str) -> Option<&RawString> {
+        let sid = self.page(page)?.texts.get(language)?;
+        self.package.texts.get(sid)
+    }
+
+    /// The text under `sid` with its markup, see `crate::flipbook::markup`. Texts without markup
+    ///   come as they are in `texts`
+    pub fn markup(&self, sid: &str) -> Option<&RawString> {
+        let package = &self.package;
+        package.markup.get(sid).or_else(|| package.texts.get(sid))
+    }
+
+    /// Every page text in `language`, as (page no., text)
+    pub fn texts(&self, language: &str) -> Vec<(usize, &RawString)> {
+        (0..self.page_count())
+            .filter_map(|page| Some((page, self.text(page, language)?)))
+            .collect()
+    }
+
+    /// `None` when the page doesn't exist or has no image
+    pub fn image(&mut self, page: usize) -> Result<Option<LoadedAsset>> {
+        let Some(fip) = self.page(page).and_then(|p| p.image.clone()) else {
+            return Ok(None);
+        };
+        self.load(&fip).map(Some)
+    }
+
+    /// The background of `page` in the variant `name`, from its own binary package if it has one.
+    ///   `None` when the page doesn't exist or has no such variant
+    pub fn variant(&mut self, page: usize, name: &str) -> Result<Option<LoadedAsset>> {
+        let Some(fip) = self.page(page).and_then(|p| p.variants.get(name)).cloned() else {
+            return Ok(None);
+        };
+        let assets = self.variants.get_mut(name).unwrap_or(&mut self.assets);
+        Ok(Some(LoadedAsset {
+            format: fip.format,
+            content: assets.read(&fip)?,
+        }))
+    }
+
+    /// `None` when the page doesn't exist or has no audio in `language`
+    pub fn audio(&mut self, page: usize, language: &str) -> Result<Option<LoadedAsset>> {
+        let fip = self
+            .page(page)
+            .and_then(|p| p.audio.get(language))
+            .and_then(|sid| self.package.audio.get(sid))
+            .cloned();
+        match fip {
+            Some(fip) => self.load(&fip).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// `None` when the page, or the block, doesn't exist or has no text in `language`
+    pub fn block_text(&self, page: usize, block: usize, language: &str) -> Option<&RawString> {
+        let sid = self.page(page)?.blocks.get(block)?.texts.get(language)?;
+        self.package.texts.get(sid)
+    }
+
+    /// `None` when the page, or the block, doesn't exist or has no audio in `language`
+    pub fn block_audio(
+        &mut self,
+        page: usize,
+        block: usize,
+        language: &str,
+    ) -> Result<Option<LoadedAsset>> {
+        let fip = self
+            .page(page)
+            .and_then(|p| p.blocks.get(block))
+            .and_then(|b| b.audio.get(language))
+            .and_then(|sid| self.package.audio.get(sid))
+            .cloned();
+        match fip {
+            Some(fip) => self.load(&fip).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The miniature embedded in the metadata, decoded
+    pub fn miniature(&self) -> Result<Vec<u8>> {
+        general_purpose::STANDARD
+            .decode(&self.package.miniature)
+            .map_err(|source| FlipbookError::InvalidMiniature {
+                path: self.path.clone(),
+                source,
+            })
+    }
+
+    /// Length of the assets, the binary package or the asset section of the container
+    pub fn assets_length(&self) -> u64 {
+        self.assets.len()
+    }
+
+    /// Reads any asset of the main binary package, `image`, `variant` and `audio` are the usual
+    ///   way in
+    pub fn load(&mut self, fip: &FilePositionInPackage) -> Result<LoadedAsset> {
+        Ok(LoadedAsset {
+            format: fip.format,
+            content: self.assets.read(fip)?,
+        })
+    }
+}
+
+/// The binary packages of `package.variant_packages`, next to the one under `path_binary`
+fn open_variants(
+    package: &FlipbookPackage,
+    path_binary: &Path,
+) -> Result<BTreeMap<VariantName, AssetReader>> {
+    let mut variants = BTreeMap::new();
+    for (name, variant_package) in &package.variant_packages {
+        let path = variant_package.path_next_to(path_binary);
+        let assets = AssetReader::open_binary(&path.to_string_lossy())?;
+        variants.insert(name.clone(), assets);
+    }
+    Ok(variants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        compile_sample, path_binary, path_metadata, sample_options, scratch_dir,
+    };
+
+    #[test]
+    fn reads_back_a_compiled_flipbook() {
+        let dir = scratch_dir("reader");
+        let mut reader = compile_sample(&dir, &sample_options());
+        assert_eq!(reader.page_count(), 3);
+        assert_eq!(reader.title("en").unwrap(), "Title");
+        assert_eq!(
+            reader.texts("en"),
+            vec![(1, &"one".to_string()), (2, &"two".to_string())]
+        );
+        let miniature = image::load_from_memory(&reader.miniature().unwrap()).unwrap();
+        assert_eq!((miniature.width(), miniature.height()), (256, 192));
+
+        assert_eq!(reader.image(0).unwrap().unwrap().content, b"image 00");
+        assert!(reader.image(1).unwrap().is_none());
+        let audio = reader.audio(2, "en").unwrap().unwrap();
+        assert_eq!(audio.format, BinaryFormat::Ogg);
+        assert_eq!(audio.content, b"audio 02");
+        assert!(reader.audio(2, "sv").unwrap().is_none());
+
+        // A truncated binary package is caught when opening
+        std::fs::write(path_binary(&dir), b"image").unwrap();
+        assert!(matches!(
+            PackageReader::open_metadata(&path_metadata(&dir)),
+            Err(FlipbookError::AssetOutOfRange { .. })
+        ));
+    }
+}
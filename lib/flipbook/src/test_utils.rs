@@ -0,0 +1,87 @@
+// Helpers shared by the tests of the different modules: a scratch directory with some fake
+//   assets, a source flipbook pointing at them and a way to compile it and read it back
+use std::path::{Path, PathBuf};
+
+use crate::compile::{compile_with_options, CompileOptions, MissingBackground};
+use crate::error::Result;
+use crate::flipbook::package::FilePositionInPackage;
+use crate::flipbook::source::FlipbookSource;
+use crate::reader::PackageReader;
+
+/// Fresh directory under the system's temp dir, with some fake assets inside. The miniature is a
+///   real 320x240 JPEG, it's decoded while compiling
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("flipbook-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, content) in [
+        ("00.jpg", "image 00"),
+        ("02.jpg", "image 02"),
+        ("02_en.ogg", "audio 02"),
+    ] {
+        std::fs::write(dir.join(file), content).unwrap();
+    }
+    image::RgbImage::from_pixel(320, 240, image::Rgb([200, 120, 40]))
+        .save(dir.join("miniature.jpg"))
+        .unwrap();
+    dir
+}
+
+/// Three pages, page 1 has no background on disk
+pub fn sample_source(dir: &Path) -> FlipbookSource {
+    let path = |f: &str| dir.join(f).to_str().unwrap().to_string();
+    let source = serde_json::json!({
+        "version": 1,
+        "languages": ["en"],
+        "default_language": "en",
+        "title": { "en": { "text": "Title" } },
+        "summary": { "en": { "text": "Summary" } },
+        "miniature": { "path": path("miniature.jpg") },
+        "pages": [
+            { "background": { "path": path("00.jpg") } },
+            { "background": { "path": path("01.jpg") }, "text": { "en": { "text": "one" } } },
+            {
+                "background": { "path": path("02.jpg") },
+                "text": { "en": { "text": "two", "audio": { "path": path("02_en.ogg") } } }
+            }
+        ]
+    });
+    serde_json::from_value(source).unwrap()
+}
+
+pub fn slice<'a>(binary: &'a [u8], fip: &FilePositionInPackage) -> &'a [u8] {
+    &binary[fip.start as usize..(fip.start + fip.length) as usize]
+}
+
+/// Where `compile_source` writes the metadata in `dir`
+pub fn path_metadata(dir: &Path) -> String {
+    dir.join("out.json").to_str().unwrap().to_string()
+}
+
+/// Where `compile_source` writes the binary package in `dir`
+pub fn path_binary(dir: &Path) -> String {
+    dir.join("out.bin").to_str().unwrap().to_string()
+}
+
+/// The options the sample compiles with: the background of page 1 isn't on disk
+pub fn sample_options() -> CompileOptions {
+    CompileOptions {
+        missing_background: MissingBackground::LeaveEmpty,
+        ..Default::default()
+    }
+}
+
+/// Compiles `source` into `dir`, see `path_metadata` and `path_binary`, and opens the result
+pub fn compile_source(
+    dir: &Path,
+    source: &FlipbookSource,
+    options: &CompileOptions,
+) -> Result<PackageReader> {
+    compile_with_options(source, &path_metadata(dir), &path_binary(dir), options)?;
+    PackageReader::open_metadata(&path_metadata(dir))
+}
+
+/// `compile_source` for `sample_source(dir)`
+pub fn compile_sample(dir: &Path, options: &CompileOptions) -> PackageReader {
+    compile_source(dir, &sample_source(dir), options).unwrap()
+}
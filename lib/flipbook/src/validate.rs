@@ -0,0 +1,773 @@
+// The `validate` module is the sanity check `compile` has always assumed somebody else did: it
+//   walks a `FlipbookSource` and reports everything that would produce a broken package (or a
+//   panic) without touching any output.
+// The report separates errors (the book can't be packaged as is) from warnings (it can, but a
+//   reader will notice something is off), each tied to the page, language and file involved.
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::compile::{CompileOptions, MissingBackground};
+use crate::flipbook::common::{FilePath, LanguageCode};
+use crate::flipbook::format::{BinaryFormat, MediaKind};
+use crate::flipbook::language;
+use crate::flipbook::layout::check_block;
+use crate::flipbook::markup;
+use crate::flipbook::source::{Asset, FlipbookSource, PageText, TextLocation};
+use crate::flipbook::timing::{self, TimingFile, TimingFormat, TIMING_TOLERANCE_MS};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum IssueKind {
+    /// A file referenced by the source doesn't exist
+    MissingFile,
+    /// A file has no extension, so its format can't be told
+    MissingExtension,
+    /// A file has an extension the client doesn't know how to decode
+    UnknownExtension,
+    /// The content of a file is in another format than its extension says
+    FormatMismatch,
+    /// An image where audio was expected, or the other way around
+    UnexpectedMediaKind,
+    /// The content of a file isn't in any format it could be told from, its extension is trusted
+    UnrecognizedContent,
+    /// There's no miniature and no page to make it from
+    NoMiniature,
+    /// A timing track can't be read, see `timing::parse`
+    InvalidTiming,
+    /// A timing track goes on after its audio has ended
+    TimingOutOfRange,
+    /// A timing track for a text without audio
+    TimingWithoutAudio,
+    /// A text block outside its page, or with a font size that can't be drawn
+    InvalidTextBlock,
+    /// A text with markup that can't be parsed, see `markup::parse`
+    InvalidMarkup,
+    /// A language that isn't a well formed BCP 47 tag of a known language, nor a name of one, or
+    ///   the ambiguous `se`. See `language`
+    InvalidLanguageTag,
+    /// A language written with a name or a code `compile` turns into its tag: `English` is `en`
+    LegacyLanguageName,
+    /// Two languages of the same list, or text, are the same one written differently
+    DuplicateLanguage,
+    /// `compile` refuses the languages for a reason no other issue tells, see
+    ///   `FlipbookSource::with_canonical_languages`
+    RefusedLanguages,
+    /// `default_language` isn't one of `languages`
+    UnknownDefaultLanguage,
+    /// A text is written in a language that isn't one of `languages`
+    UndeclaredLanguage,
+    /// A language in `languages` doesn't have a single text in the whole book
+    LanguageWithoutText,
+    /// A text exists, but not in every language of the book, nor in its fallbacks
+    MissingTranslation,
+    /// A fallback chain names a language that isn't one of `languages`, or the language itself
+    InvalidFallback,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+    /// `None` when the issue isn't related to a page: title, summary, miniature, ..
+    pub page: Option<usize>,
+    /// Set when the issue is about one of the text blocks of `page`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<usize>,
+    pub language: Option<LanguageCode>,
+    pub path: Option<FilePath>,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {:?}", self.severity, self.kind)?;
+        if let Some(page) = self.page {
+            write!(f, " page: {page}")?;
+        }
+        if let Some(block) = self.block {
+            write!(f, " block: {block}")?;
+        }
+        if let Some(language) = &self.language {
+            write!(f, " language: `{language}`")?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, " path: `{path}`")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where an asset referenced by the source was looked for
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ResolvedAsset {
+    pub page: Option<usize>,
+    pub language: Option<LanguageCode>,
+    /// As written in the source
+    pub path: FilePath,
+    /// See `FlipbookSource::resolve`
+    pub resolved: FilePath,
+}
+
+/// How many of the texts of the book a language has: its own translation, one taken from a
+///   fallback or none at all
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct LanguageCoverage {
+    pub native: usize,
+    pub fallback: usize,
+    pub missing: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+    /// Every asset of the source, in the order they were checked
+    pub assets: Vec<ResolvedAsset>,
+    /// For every language of the book, title, summary and texts of the pages included
+    pub coverage: BTreeMap<LanguageCode, LanguageCoverage>,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Issue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+    }
+
+    /// A source with errors shouldn't reach `compile`
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        kind: IssueKind,
+        page: Option<usize>,
+        language: Option<&str>,
+        path: Option<&str>,
+    ) {
+        self.issues.push(Issue {
+            severity,
+            kind,
+            page,
+            block: None,
+            language: language.map(str::to_string),
+            path: path.map(str::to_string),
+        });
+    }
+}
+
+/// Checks a source flipbook before it's handed to `compile`. It never fails: everything found is
+///   reported in the returned `ValidationReport`
+pub fn validate(source: &FlipbookSource) -> ValidationReport {
+    validate_with_options(source, &CompileOptions::default())
+}
+
+/// Same as `validate` but for a source compiled with `options`: a missing background is only a
+///   warning when `options.missing_background` doesn't fail on it, and a format mismatch is an
+///   error with `options.strict_formats`
+pub fn validate_with_options(
+    source: &FlipbookSource,
+    options: &CompileOptions,
+) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let missing_background = match options.missing_background {
+        MissingBackground::Fail => Severity::Error,
+        MissingBackground::Placeholder | MissingBackground::LeaveEmpty => Severity::Warning,
+    };
+
+    check_language_tags(source, &mut report);
+    // The rest is checked the way it's compiled, with canonical tags. When `compile` would refuse
+    //   them it's checked as written
+    let canonical = source.with_canonical_languages();
+    let source = canonical.as_ref().unwrap_or(source);
+
+    validate_languages(source, &mut report);
+    if canonical.is_err() && !report.has_errors() {
+        report.push(
+            Severity::Error,
+            IssueKind::RefusedLanguages,
+            None,
+            None,
+            None,
+        );
+    }
+
+    // Without one it's made from the first page, checked along the rest
+    match &source.miniature {
+        Some(miniature) => check_file(
+            &mut report,
+            source,
+            None,
+            None,
+            &miniature.path,
+            MediaKind::Image,
+            Severity::Error,
+        ),
+        None if source.pages.is_empty() => {
+            report.push(Severity::Error, IssueKind::NoMiniature, None, None, None)
+        }
+        None => {}
+    }
+    check_texts(&mut report, source, None, &source.title);
+    check_texts(&mut report, source, None, &source.summary);
+
+    for (pos, page) in source.pages.iter().enumerate() {
+        check_file(
+            &mut report,
+            source,
+            Some(pos),
+            None,
+            &page.background.path,
+            MediaKind::Image,
+            missing_background,
+        );
+        if let Some(text) = &page.text {
+            check_texts(&mut report, source, Some(pos), text);
+        }
+        for (block, text_block) in page.blocks.iter().enumerate() {
+            // Issues are found the same way as in the page text, then tied to the block
+            let first_issue = report.issues.len();
+            if check_block(&text_block.rect, text_block.font_size).is_some() {
+                report.push(
+                    Severity::Error,
+                    IssueKind::InvalidTextBlock,
+                    Some(pos),
+                    None,
+                    None,
+                );
+            }
+            check_texts(&mut report, source, Some(pos), &text_block.text);
+            for issue in &mut report.issues[first_issue..] {
+                issue.block = Some(block);
+            }
+        }
+    }
+
+    // Found as any other file issue, `compile` refuses them instead of warning
+    if options.strict_formats {
+        for issue in &mut report.issues {
+            if issue.kind == IssueKind::FormatMismatch {
+                issue.severity = Severity::Error;
+            }
+        }
+    }
+
+    report
+}
+
+/// Every language as it's written in the source, see `language::canonical`
+fn check_language_tags(source: &FlipbookSource, report: &mut ValidationReport) {
+    let mut groups: Vec<(Option<TextLocation>, Vec<&str>)> = vec![
+        (None, source.languages.iter().map(String::as_str).collect()),
+        (None, vec![source.default_language.as_str()]),
+        (None, source.fallbacks.keys().map(String::as_str).collect()),
+    ];
+    for chain in source.fallbacks.values() {
+        groups.push((None, chain.iter().map(String::as_str).collect()));
+    }
+    let texts = [&source.title, &source.summary].map(|text| (None, text));
+    let located = source
+        .located_texts()
+        .into_iter()
+        .map(|(l, text)| (Some(l), text));
+    for (location, text) in texts.into_iter().chain(located) {
+        groups.push((location, text.0.keys().map(String::as_str).collect()));
+    }
+
+    for (location, codes) in groups {
+        let first_issue = report.issues.len();
+        let page = location.map(|l| l.page);
+        let mut seen = vec![];
+        for code in codes {
+            match language::canonical(code) {
+                Err(_) => report.push(
+                    Severity::Error,
+                    IssueKind::InvalidLanguageTag,
+                    page,
+                    Some(code),
+                    None,
+                ),
+                Ok(canonical) if seen.contains(&canonical) => report.push(
+                    Severity::Error,
+                    IssueKind::DuplicateLanguage,
+                    page,
+                    Some(code),
+                    None,
+                ),
+                Ok(canonical) => {
+                    if canonical != code {
+                        report.push(
+                            Severity::Warning,
+                            IssueKind::LegacyLanguageName,
+                            page,
+                            Some(code),
+                            None,
+                        );
+                    }
+                    seen.push(canonical);
+                }
+            }
+        }
+        for issue in &mut report.issues[first_issue..] {
+            issue.block = location.and_then(|l| l.block);
+        }
+    }
+}
+
+fn validate_languages(source: &FlipbookSource, report: &mut ValidationReport) {
+    if !source.languages.contains(&source.default_language) {
+        report.push(
+            Severity::Error,
+            IssueKind::UnknownDefaultLanguage,
+            None,
+            Some(&source.default_language),
+            None,
+        );
+    }
+
+    for (lang, chain) in &source.fallbacks {
+        let invalid = !source.languages.contains(lang)
+            || chain
+                .iter()
+                .any(|fallback| fallback == lang || !source.languages.contains(fallback));
+        if invalid {
+            report.push(
+                Severity::Error,
+                IssueKind::InvalidFallback,
+                None,
+                Some(lang),
+                None,
+            );
+        }
+    }
+
+    let pages_texts = source.pages_text();
+    for lang in &source.languages {
+        let in_title = source.title.0.contains_key(lang);
+        let in_summary = source.summary.0.contains_key(lang);
+        let in_pages = pages_texts.iter().any(|pt| &pt.1 == lang);
+        if !(in_title || in_summary || in_pages) {
+            report.push(
+                Severity::Warning,
+                IssueKind::LanguageWithoutText,
+                None,
+                Some(lang),
+                None,
+            );
+        }
+    }
+}
+
+/// Every text should exist in every language of the book, or its fallbacks. Audio is optional
+///   but when present it has to be there
+fn check_texts(
+    report: &mut ValidationReport,
+    source: &FlipbookSource,
+    page: Option<usize>,
+    text: &PageText,
+) {
+    for lang in &source.languages {
+        let coverage = report.coverage.entry(lang.clone()).or_default();
+        match source.text_for(text, lang) {
+            Some((from, _)) if from == lang => coverage.native += 1,
+            Some(_) => coverage.fallback += 1,
+            None => {
+                coverage.missing += 1;
+                report.push(
+                    Severity::Warning,
+                    IssueKind::MissingTranslation,
+                    page,
+                    Some(lang),
+                    None,
+                );
+            }
+        }
+    }
+
+    for (lang, asset) in &text.0 {
+        if markup::parse(&asset.text).is_err() {
+            report.push(
+                Severity::Error,
+                IssueKind::InvalidMarkup,
+                page,
+                Some(lang),
+                None,
+            );
+        }
+        if !source.languages.contains(lang) {
+            report.push(
+                Severity::Warning,
+                IssueKind::UndeclaredLanguage,
+                page,
+                Some(lang),
+                None,
+            );
+        }
+        if let Some(audio) = &asset.audio {
+            check_file(
+                report,
+                source,
+                page,
+                Some(lang),
+                &audio.path,
+                MediaKind::Audio,
+                Severity::Error,
+            );
+        }
+        for file in &asset.timings {
+            check_timing(report, source, page, lang, asset, file);
+        }
+    }
+}
+
+/// Timing tracks are read, and measured against their audio, the way `compile` does
+fn check_timing(
+    report: &mut ValidationReport,
+    source: &FlipbookSource,
+    page: Option<usize>,
+    language: &str,
+    asset: &Asset,
+    file: &TimingFile,
+) {
+    let resolved = source.resolve(&file.path);
+    report.assets.push(ResolvedAsset {
+        page,
+        language: Some(language.to_string()),
+        path: file.path.clone(),
+        resolved: resolved.clone(),
+    });
+    let mut push =
+        |severity, kind| report.push(severity, kind, page, Some(language), Some(&resolved));
+
+    let Some(format) = file.format.or_else(|| TimingFormat::from_path(&resolved)) else {
+        push(Severity::Error, IssueKind::UnknownExtension);
+        return;
+    };
+    let Ok(content) = std::fs::read_to_string(&resolved) else {
+        push(Severity::Error, IssueKind::MissingFile);
+        return;
+    };
+    let Ok(track) = timing::parse(&content, format, file.kind) else {
+        push(Severity::Error, IssueKind::InvalidTiming);
+        return;
+    };
+
+    let Some(audio) = &asset.audio else {
+        push(Severity::Warning, IssueKind::TimingWithoutAudio);
+        return;
+    };
+    let audio = source.resolve(&audio.path);
+    // An audio that can't be measured is reported by `check_file`
+    let duration_ms = std::fs::read(&audio).ok().and_then(|content| {
+        let format = BinaryFormat::sniff(&content).or_else(|| BinaryFormat::from_path(&audio))?;
+        crate::compile::audio_duration_ms(content, format)
+    });
+    if duration_ms.is_some_and(|d| track.end_ms() > d + TIMING_TOLERANCE_MS) {
+        push(Severity::Error, IssueKind::TimingOutOfRange);
+    }
+}
+
+/// Issues about a file carry its resolved path, the one that was actually checked
+fn check_file(
+    report: &mut ValidationReport,
+    source: &FlipbookSource,
+    page: Option<usize>,
+    language: Option<&str>,
+    path: &str,
+    kind: MediaKind,
+    missing: Severity,
+) {
+    let resolved = source.resolve(path);
+    report.assets.push(ResolvedAsset {
+        page,
+        language: language.map(str::to_string),
+        path: path.to_string(),
+        resolved: resolved.clone(),
+    });
+
+    let as_path = Path::new(&resolved);
+    if as_path.is_file() {
+        check_content(report, page, language, &resolved, kind);
+    } else {
+        report.push(
+            missing,
+            IssueKind::MissingFile,
+            page,
+            language,
+            Some(&resolved),
+        );
+    }
+
+    match as_path.extension().and_then(|e| e.to_str()) {
+        None => report.push(
+            Severity::Error,
+            IssueKind::MissingExtension,
+            page,
+            language,
+            Some(&resolved),
+        ),
+        Some(ext)
+            if !BinaryFormat::extensions(kind).any(|known| known.eq_ignore_ascii_case(ext)) =>
+        {
+            report.push(
+                Severity::Warning,
+                IssueKind::UnknownExtension,
+                page,
+                language,
+                Some(&resolved),
+            )
+        }
+        Some(_) => {}
+    }
+}
+
+/// The content decides the format, see `BinaryFormat::sniff`. A file that can't be read is left
+///   for `compile` to report
+fn check_content(
+    report: &mut ValidationReport,
+    page: Option<usize>,
+    language: Option<&str>,
+    resolved: &str,
+    kind: MediaKind,
+) {
+    let Ok(sniffed) = BinaryFormat::sniff_file(resolved) else {
+        return;
+    };
+    let issue = match sniffed {
+        None => Some((Severity::Warning, IssueKind::UnrecognizedContent)),
+        Some(format) if format.kind() != kind => {
+            Some((Severity::Error, IssueKind::UnexpectedMediaKind))
+        }
+        Some(format) if BinaryFormat::from_path(resolved) != Some(format) => {
+            Some((Severity::Warning, IssueKind::FormatMismatch))
+        }
+        Some(_) => None,
+    };
+    if let Some((severity, kind)) = issue {
+        report.push(severity, kind, page, language, Some(resolved));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"{
+        "version": 1,
+        "languages": ["en", "es"],
+        "default_language": "sv",
+        "title": { "en": { "text": "Title" }, "es": { "text": "Título" } },
+        "summary": { "en": { "text": "Summary" } },
+        "miniature": { "path": "./does_not_exist/miniature.jpg" },
+        "pages": [
+            { "background": { "path": "./does_not_exist/00.bmp" } },
+            {
+                "background": { "path": "./does_not_exist/01" },
+                "text": {
+                    "en": { "text": "page", "audio": { "path": "./does_not_exist/01_en.ogg" } },
+                    "de": { "text": "Seite" }
+                }
+            }
+        ]
+    }"#;
+
+    fn issues_of(report: &ValidationReport, kind: IssueKind) -> Vec<&Issue> {
+        report.issues.iter().filter(|i| i.kind == kind).collect()
+    }
+
+    #[test]
+    fn reports_issues_with_context() {
+        let source: FlipbookSource = serde_json::from_str(SOURCE).unwrap();
+        let report = validate(&source);
+        assert!(report.has_errors());
+
+        // miniature + 2 backgrounds + 1 audio
+        let missing = issues_of(&report, IssueKind::MissingFile);
+        assert_eq!(missing.len(), 4);
+        assert!(missing.iter().any(|i| i.page == Some(1)
+            && i.language.as_deref() == Some("en")
+            && i.path.as_deref() == Some("./does_not_exist/01_en.ogg")));
+        assert!(missing.iter().all(|i| i.severity == Severity::Error));
+
+        // Compiled leaving the pages empty, only the backgrounds are forgiven
+        let options = CompileOptions {
+            missing_background: MissingBackground::LeaveEmpty,
+            ..Default::default()
+        };
+        let leave_empty = validate_with_options(&source, &options);
+        let missing = issues_of(&leave_empty, IssueKind::MissingFile);
+        let warnings = missing.iter().filter(|i| i.severity == Severity::Warning);
+        assert_eq!(warnings.count(), 2);
+
+        assert_eq!(
+            issues_of(&report, IssueKind::UnknownDefaultLanguage).len(),
+            1
+        );
+        assert_eq!(
+            issues_of(&report, IssueKind::MissingExtension)[0].page,
+            Some(1)
+        );
+        assert_eq!(
+            issues_of(&report, IssueKind::UnknownExtension)[0].page,
+            Some(0)
+        );
+
+        let undeclared = issues_of(&report, IssueKind::UndeclaredLanguage);
+        assert_eq!(undeclared[0].language.as_deref(), Some("de"));
+
+        // summary in Spanish and page 1 in Spanish
+        let missing_translation = issues_of(&report, IssueKind::MissingTranslation);
+        assert_eq!(missing_translation.len(), 2);
+        assert!(missing_translation
+            .iter()
+            .all(|i| i.severity == Severity::Warning && i.language.as_deref() == Some("es")));
+    }
+
+    #[test]
+    fn fallbacks_cover_missing_translations() {
+        let mut source: serde_json::Value = serde_json::from_str(SOURCE).unwrap();
+        source["fallbacks"] = serde_json::json!({ "es": ["en"], "en": ["fr"] });
+        let source: FlipbookSource = serde_json::from_value(source).unwrap();
+        let report = validate(&source);
+
+        assert!(issues_of(&report, IssueKind::MissingTranslation).is_empty());
+        let invalid = issues_of(&report, IssueKind::InvalidFallback);
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].language.as_deref(), Some("en"));
+
+        // title, summary and page 1
+        let coverage = |native, fallback, missing| LanguageCoverage {
+            native,
+            fallback,
+            missing,
+        };
+        assert_eq!(report.coverage["en"], coverage(3, 0, 0));
+        assert_eq!(report.coverage["es"], coverage(1, 2, 0));
+    }
+
+    #[test]
+    fn legacy_and_unknown_language_tags() {
+        let mut source: serde_json::Value = serde_json::from_str(SOURCE).unwrap();
+        source["languages"] = serde_json::json!(["English", "es", "sv"]);
+        source["default_language"] = serde_json::json!("en");
+        source["pages"][1]["text"]["SV"] = serde_json::json!({ "text": "sida" });
+        let legacy: FlipbookSource = serde_json::from_value(source.clone()).unwrap();
+        let report = validate(&legacy);
+
+        let names = issues_of(&report, IssueKind::LegacyLanguageName);
+        assert_eq!(names.len(), 2);
+        assert!(names.iter().all(|i| i.severity == Severity::Warning));
+        assert_eq!(names[0].language.as_deref(), Some("English"));
+        // Checked as compiled: `English` is `en` and `SV` is `sv`
+        assert!(issues_of(&report, IssueKind::UnknownDefaultLanguage).is_empty());
+        assert!(issues_of(&report, IssueKind::InvalidLanguageTag).is_empty());
+        let undeclared = issues_of(&report, IssueKind::UndeclaredLanguage);
+        assert_eq!(undeclared.len(), 1);
+        assert_eq!(undeclared[0].language.as_deref(), Some("de"));
+
+        source["title"]["Inglés"] = serde_json::json!({ "text": "Title" });
+        source["pages"][1]["text"]["se"] = serde_json::json!({ "text": "nav" });
+        source["pages"][1]["text"]["ko"] = serde_json::json!({ "text": "쪽" });
+        let refused: FlipbookSource = serde_json::from_value(source).unwrap();
+        assert!(refused.with_canonical_languages().is_err());
+        let report = validate(&refused);
+
+        // `Inglés` comes first, `en` is the one written twice
+        let duplicate = issues_of(&report, IssueKind::DuplicateLanguage);
+        assert_eq!(duplicate.len(), 1);
+        assert_eq!(
+            (duplicate[0].page, duplicate[0].language.as_deref()),
+            (None, Some("en"))
+        );
+        // Swedish or Northern Sami, it can't be told, and a language nobody reads
+        let invalid = issues_of(&report, IssueKind::InvalidLanguageTag);
+        let invalid: Vec<_> = invalid
+            .iter()
+            .map(|i| (i.page, i.language.as_deref()))
+            .collect();
+        assert_eq!(invalid, [(Some(1), Some("ko")), (Some(1), Some("se"))]);
+        // Both already tell why `compile` refuses the source
+        assert!(issues_of(&report, IssueKind::RefusedLanguages).is_empty());
+    }
+
+    #[test]
+    fn resolves_relative_paths_against_the_source() {
+        let dir = crate::test_utils::scratch_dir("validate-relative");
+        let book = dir.join("book");
+        std::fs::create_dir_all(&book).unwrap();
+        for file in ["miniature.jpg", "00.jpg"] {
+            std::fs::copy(dir.join(file), book.join(file)).unwrap();
+        }
+        let path_source = book.join("source.json");
+        std::fs::write(
+            &path_source,
+            r#"{
+                "version": 1,
+                "languages": ["en"],
+                "default_language": "en",
+                "title": { "en": { "text": "Title" } },
+                "summary": { "en": { "text": "Summary" } },
+                "miniature": { "path": "miniature.jpg" },
+                "pages": [ { "background": { "path": "./00.jpg" } } ]
+            }"#,
+        )
+        .unwrap();
+
+        let source = FlipbookSource::from_file(path_source.to_str().unwrap()).unwrap();
+        let report = validate(&source);
+        assert!(!report.has_errors());
+        assert_eq!(report.assets.len(), 2);
+        assert_eq!(report.assets[1].path, "./00.jpg");
+        assert!(Path::new(&report.assets[1].resolved).is_absolute());
+        assert_eq!(
+            std::fs::read(&report.assets[1].resolved).unwrap(),
+            b"image 00"
+        );
+
+        // The same source against another root
+        let source = FlipbookSource::from_file_with_asset_root(
+            path_source.to_str().unwrap(),
+            dir.join("elsewhere").to_str().unwrap(),
+        )
+        .unwrap();
+        let report = validate(&source);
+        assert_eq!(issues_of(&report, IssueKind::MissingFile).len(), 2);
+    }
+
+    #[test]
+    fn strict_formats_make_mismatches_errors() {
+        use crate::test_utils::{sample_options, sample_source, scratch_dir};
+
+        let dir = scratch_dir("validate-strict");
+        std::fs::copy(dir.join("miniature.jpg"), dir.join("00.png")).unwrap();
+        let mut source = sample_source(&dir);
+        source.pages[0].background.path = dir.join("00.png").to_str().unwrap().to_string();
+
+        let report = validate_with_options(&source, &sample_options());
+        let mismatches = issues_of(&report, IssueKind::FormatMismatch);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].severity, Severity::Warning);
+        assert!(!report.has_errors());
+
+        let strict = CompileOptions {
+            strict_formats: true,
+            ..sample_options()
+        };
+        let report = validate_with_options(&source, &strict);
+        let mismatches = issues_of(&report, IssueKind::FormatMismatch);
+        assert_eq!(mismatches[0].severity, Severity::Error);
+        assert!(report.has_errors());
+    }
+}
@@ -0,0 +1,235 @@
+// The `verify` module checks a binary package against the metadata it was compiled with: every
+//   asset is sliced out of the `.bin` exactly as the client would do it and its digest compared
+//   to the one recorded by `compile`.
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+
+use crate::compile::to_digest;
+use crate::error::{FlipbookError, Result};
+use crate::flipbook::package::{
+    Digest, FilePositionInPackage, FlipbookPackage, StringID, VariantName,
+};
+
+/// Size of the chunks read from the binary package while hashing
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How an asset is reached from the metadata
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum AssetId {
+    PageImage { page: usize },
+    Audio { string_id: StringID },
+    Variant { page: usize, name: VariantName },
+}
+
+impl Display for AssetId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssetId::PageImage { page } => write!(f, "image of page {page}"),
+            AssetId::Audio { string_id } => write!(f, "audio `{string_id}`"),
+            AssetId::Variant { page, name } => write!(f, "`{name}` image of page {page}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Corruption {
+    /// The asset goes beyond the end of the binary package
+    OutOfRange {
+        package_length: u64,
+    },
+    DigestMismatch {
+        expected: Digest,
+        found: Digest,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CorruptedAsset {
+    pub asset: AssetId,
+    pub position: FilePositionInPackage,
+    pub corruption: Corruption,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VerificationReport {
+    /// `None` when the metadata has no digest for the whole package
+    pub package_digest_matches: Option<bool>,
+    /// The same for the variants with a binary package of their own, the ones with a digest
+    pub variant_packages_match: BTreeMap<VariantName, bool>,
+    pub verified_assets: usize,
+    /// Assets without a digest in the metadata, only their range was checked
+    pub unverified_assets: usize,
+    pub corrupted: Vec<CorruptedAsset>,
+}
+
+impl VerificationReport {
+    pub fn is_intact(&self) -> bool {
+        self.corrupted.is_empty()
+            && self.package_digest_matches != Some(false)
+            && self.variant_packages_match.values().all(|matches| *matches)
+    }
+}
+
+/// Verifies the binary package under `path_binary` against `package`, and the packages of the
+///   variants next to it. Only failing to read a binary package is an error, corrupted assets are
+///   listed in the returned report
+pub fn verify(package: &FlipbookPackage, path_binary: &str) -> Result<VerificationReport> {
+    let mut report = VerificationReport::default();
+    report.package_digest_matches = verify_binary(
+        &mut report,
+        path_binary,
+        package.binary_package_digest.as_ref(),
+        assets_in_package(package, None),
+    )?;
+    for (name, variant_package) in &package.variant_packages {
+        let path = variant_package.path_next_to(Path::new(path_binary));
+        let matches = verify_binary(
+            &mut report,
+            &path.to_string_lossy(),
+            variant_package.digest.as_ref(),
+            assets_in_package(package, Some(name)),
+        )?;
+        if let Some(matches) = matches {
+            report.variant_packages_match.insert(name.clone(), matches);
+        }
+    }
+    Ok(report)
+}
+
+/// Checks `assets` against the binary package under `path_binary`, whether it matches `digest`
+///   when there's one
+fn verify_binary(
+    report: &mut VerificationReport,
+    path_binary: &str,
+    digest: Option<&Digest>,
+    assets: Vec<(AssetId, &FilePositionInPackage)>,
+) -> Result<Option<bool>> {
+    let read_error = |source| FlipbookError::PackageRead {
+        path: path_binary.to_string(),
+        source,
+    };
+    let mut f = File::open(path_binary).map_err(read_error)?;
+    let package_length = f.metadata().map_err(read_error)?.len();
+
+    let mut matches = None;
+    if let Some(expected) = digest {
+        let found = hash_range(&mut f, 0, package_length).map_err(read_error)?;
+        matches = Some(&found == expected);
+    }
+
+    for (asset, position) in assets {
+        if position.start.saturating_add(position.length) > package_length {
+            report.corrupted.push(CorruptedAsset {
+                asset,
+                position: position.clone(),
+                corruption: Corruption::OutOfRange { package_length },
+            });
+            continue;
+        }
+
+        let Some(expected) = &position.digest else {
+            report.unverified_assets += 1;
+            continue;
+        };
+
+        let found = hash_range(&mut f, position.start, position.length).map_err(read_error)?;
+        if &found == expected {
+            report.verified_assets += 1;
+        } else {
+            report.corrupted.push(CorruptedAsset {
+                asset,
+                position: position.clone(),
+                corruption: Corruption::DigestMismatch {
+                    expected: expected.clone(),
+                    found,
+                },
+            });
+        }
+    }
+
+    Ok(matches)
+}
+
+/// Every asset the metadata points at in a binary package: the main one for `None`, the one of
+///   the variant otherwise
+fn assets_in_package<'a>(
+    package: &'a FlipbookPackage,
+    variant_package: Option<&str>,
+) -> Vec<(AssetId, &'a FilePositionInPackage)> {
+    let mut assets = vec![];
+    if variant_package.is_none() {
+        for (page, p) in package.pages.iter().enumerate() {
+            if let Some(image) = &p.image {
+                assets.push((AssetId::PageImage { page }, image));
+            }
+        }
+        for (string_id, audio) in &package.audio {
+            assets.push((
+                AssetId::Audio {
+                    string_id: string_id.clone(),
+                },
+                audio,
+            ));
+        }
+    }
+    for (page, p) in package.pages.iter().enumerate() {
+        for (name, variant) in &p.variants {
+            let own_package = package.variant_packages.contains_key(name);
+            if variant_package.map_or(!own_package, |package| package == name) {
+                let name = name.clone();
+                assets.push((AssetId::Variant { page, name }, variant));
+            }
+        }
+    }
+    assets
+}
+
+pub(crate) fn hash_range(f: &mut File, start: u64, length: u64) -> std::io::Result<Digest> {
+    f.seek(SeekFrom::Start(start))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+    let mut chunk = f.take(length);
+    loop {
+        let read = chunk.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(to_digest(hasher))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{compile_sample, path_binary, sample_options, scratch_dir};
+
+    #[test]
+    fn detects_corrupted_assets() {
+        let dir = scratch_dir("verify");
+        let package = compile_sample(&dir, &sample_options()).package().clone();
+        let path_binary = path_binary(&dir);
+
+        let report = verify(&package, &path_binary).unwrap();
+        assert!(report.is_intact());
+        assert_eq!(report.verified_assets, 3);
+
+        // Flip a byte of the image in page 2
+        let mut binary = std::fs::read(&path_binary).unwrap();
+        let image = package.pages[2].image.as_ref().unwrap();
+        binary[image.start as usize] ^= 0xff;
+        std::fs::write(&path_binary, binary).unwrap();
+
+        let report = verify(&package, &path_binary).unwrap();
+        assert!(!report.is_intact());
+        assert_eq!(report.package_digest_matches, Some(false));
+        assert_eq!(report.corrupted.len(), 1);
+        assert_eq!(report.corrupted[0].asset, AssetId::PageImage { page: 2 });
+    }
+}
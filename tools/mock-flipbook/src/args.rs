@@ -14,6 +14,18 @@ pub enum Language {
     Swedish,
 }
 
+impl Language {
+    /// How the flipbooks write it, see `flipbook::flipbook::language`
+    pub fn tag(&self) -> flipbook::flipbook::common::LanguageCode {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::Swedish => "sv",
+        }
+        .to_string()
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
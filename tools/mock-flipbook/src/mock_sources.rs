@@ -46,11 +46,11 @@ impl MockCatalog {
         let v: Vec<Language> = Language::iter().map(|i| i).collect();
         let mut rng = thread_rng();
         let lang = v.choose(&mut rng).expect("Should have languages, yo");
-        format!("{:#?}", lang)
+        lang.tag()
     }
 
     pub fn get_languages(&self) -> Vec<flipbook::flipbook::common::LanguageCode> {
-        Language::iter().map(|i| format!("{:#?}", i)).collect()
+        Language::iter().map(|i| i.tag()).collect()
     }
 
     pub fn get_image(&self) -> Option<source::Image> {
@@ -81,9 +81,10 @@ impl MockCatalog {
         if let Some(line) = self.localized_lines.choose(&mut rng) {
             let mut pt: source::PageText = source::PageText::default();
             for (k, v) in line {
-                let lang = format!("{:#?}", k);
+                let lang = k.tag();
                 let asset = source::Asset {
                     text: v.text.clone(),
+                    timings: vec![],
                     audio: match &v.tts {
                         Some(pbt) => {
                             if let Some(pbt) = pbt.to_str() {
@@ -109,7 +110,11 @@ impl MockCatalog {
         let background = self.get_image_n(page_n).unwrap();
         let text = self.get_page_text();
 
-        source::SourcePage { background, text }
+        source::SourcePage {
+            background,
+            text,
+            blocks: vec![],
+        }
     }
 
     pub fn build_flipbook(&self, page_range: &PageRange) -> source::FlipbookSource {
@@ -126,14 +131,17 @@ impl MockCatalog {
             version: self.get_metadata_version(),
             languages: self.get_languages(),
             default_language: self.get_default_lang(),
+            fallbacks: Default::default(),
             title: self
                 .get_page_text()
                 .expect("No page text found? Check sources"),
             summary: self
                 .get_page_text()
                 .expect("No page text found? Check sources"),
-            miniature: self.get_image().expect("No image found? Check sources"),
+            miniature: Some(self.get_image().expect("No image found? Check sources")),
             pages: built_pages,
+            // The paths of the mock assets are used as they are
+            asset_root: None,
         }
     }
 }